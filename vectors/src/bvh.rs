@@ -0,0 +1,144 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::ray::*;
+
+// A node of a bounding volume hierarchy. Each node owns two subtrees and the box enclosing both,
+// so a ray that misses the box can skip everything below it.
+pub struct BvhNode {
+    left: Box<HittableEnum>,
+    right: Box<HittableEnum>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    // Builds a hierarchy over the given objects. A single object is returned as is,
+    // since wrapping it in a node would only add a redundant box test.
+    pub fn build(mut objects: Vec<HittableEnum>) -> HittableEnum {
+        assert!(!objects.is_empty(), "cannot build a BVH without objects");
+        if objects.len() == 1 {
+            return objects.pop().unwrap();
+        }
+
+        let bbox = objects.iter().fold(Aabb::EMPTY, |acc, object| {
+            Aabb::enclosing(&acc, &object.bounding_box())
+        });
+        let axis = bbox.longest_axis();
+        objects.sort_by(|a, b| {
+            a.bounding_box()
+                .centroid(axis)
+                .total_cmp(&b.bounding_box().centroid(axis))
+        });
+
+        let right = objects.split_off(Self::split_index(&objects));
+        HittableEnum::BvhNode(BvhNode {
+            left: Box::new(Self::build(objects)),
+            right: Box::new(Self::build(right)),
+            bbox,
+        })
+    }
+
+    // Picks the split of the sorted objects with the lowest surface area heuristic cost,
+    // falling back to the midpoint when the costs can't be compared (e.g. unbounded objects).
+    fn split_index(objects: &[HittableEnum]) -> usize {
+        let n = objects.len();
+        let boxes: Vec<Aabb> = objects.iter().map(|o| o.bounding_box()).collect();
+
+        // right_areas[i] is the area of the box enclosing objects[i..]
+        let mut right_areas = vec![0.0; n];
+        let mut acc = Aabb::EMPTY;
+        for i in (1..n).rev() {
+            acc = Aabb::enclosing(&acc, &boxes[i]);
+            right_areas[i] = acc.surface_area();
+        }
+
+        let mut best = (n / 2, f64::INFINITY);
+        let mut acc = Aabb::EMPTY;
+        for i in 1..n {
+            acc = Aabb::enclosing(&acc, &boxes[i - 1]);
+            let cost = acc.surface_area() * i as f64 + right_areas[i] * (n - i) as f64;
+            if cost < best.1 {
+                best = (i, cost);
+            }
+        }
+        best.0
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        if !self.bbox.hit(ray, ray_t) {
+            return false;
+        }
+
+        let hit_left = self.left.hit(ray, ray_t, rec);
+        let closest = if hit_left { rec.t } else { ray_t.max };
+        let hit_right = self.right.hit(ray, Interval::new(ray_t.min, closest), rec);
+
+        hit_left || hit_right
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::*;
+    use crate::planar::*;
+    use crate::utils::*;
+    use crate::vec3::*;
+
+    // The hierarchy must find exactly the hits a linear search over the same objects does,
+    // including for unbounded objects whose box centroids are NaN.
+    #[test]
+    fn test_bvh_matches_list() {
+        let mut rng = seeded_rng(3);
+        let objects = || {
+            let mut rng = seeded_rng(11);
+            let mut objects: Vec<HittableEnum> = (0..200)
+                .map(|_| {
+                    HittableEnum::Sphere(Sphere::new(
+                        random_with_range(&mut rng, -10.0, 10.0),
+                        random_double_with_range(&mut rng, 0.05, 1.0),
+                        Material::None,
+                    ))
+                })
+                .collect();
+            objects.push(HittableEnum::Plane(Plane::new(
+                Vec3(0.0, -8.0, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+                Material::None,
+            )));
+            objects.push(HittableEnum::Plane(Plane::new(
+                Vec3(0.0, 0.0, -9.0),
+                Vec3(0.3, 0.2, 1.0),
+                Material::None,
+            )));
+            objects
+        };
+        let list = HittableList { objects: objects() };
+        let bvh = HittableList { objects: objects() }.into_bvh();
+        assert_eq!(bvh.objects.len(), 1);
+
+        let mut hits = 0;
+        for _ in 0..5000 {
+            let ray = Ray::new(
+                random_with_range(&mut rng, -12.0, 12.0),
+                random_unit_vector(&mut rng),
+            );
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+            let (mut expected, mut actual) = (HitRecord::new(), HitRecord::new());
+            let hit = list.hit(&ray, ray_t, &mut expected);
+            assert_eq!(bvh.hit(&ray, ray_t, &mut actual), hit);
+            if hit {
+                hits += 1;
+                assert_eq!(actual.t, expected.t);
+                assert_eq!(actual.point.x(), expected.point.x());
+            }
+        }
+        assert!(hits > 1000);
+    }
+}
@@ -1,15 +1,111 @@
+use crate::framebuffer::*;
 use crate::hittable::*;
 use crate::interval::*;
 use crate::ray::*;
-use crate::utils::*;
+use crate::sampler::*;
 use crate::vec3::*;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+// What a ray sees when it escapes the scene without hitting anything.
+#[derive(Debug, Clone, Copy)]
+pub enum Background {
+    Solid(Color),
+    // vertical blend from bottom (looking straight down) to top (looking straight up)
+    Gradient { bottom: Color, top: Color },
+    None,
+}
+
+impl Background {
+    // The white-to-blue sky the renderer has always used.
+    pub const SKY: Background = Background::Gradient {
+        bottom: Vec3(1.0, 1.0, 1.0),
+        top: Vec3(0.5, 0.7, 1.0),
+    };
+
+    pub fn color(&self, dir: Vec3) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient { bottom, top } => {
+                let unit_direction = dir.unit_vector();
+                let a = 0.5 * (unit_direction.y() + 1.0);
+                *bottom * (1.0 - a) + *top * a
+            }
+            Background::None => Vec3(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+// One rendered pixel: the averaged color plus what the ray through the pixel center hit first.
+struct Pixel {
+    color: Color,
+    depth: f64,
+    normal: Vec3,
+    samples: i32,
+}
+
+// Running mean and variance of the sample luminances of a pixel (Welford's algorithm).
+struct PixelStats {
+    count: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl PixelStats {
+    fn new() -> Self {
+        Self {
+            count: 0.0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+
+    fn add(&mut self, color: Color) {
+        let luminance = 0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z();
+        self.count += 1.0;
+        let delta = luminance - self.mean;
+        self.mean += delta / self.count;
+        self.m2 += delta * (luminance - self.mean);
+    }
+
+    // Standard error of the mean luminance after gamma correction. The output stores
+    // sqrt(luminance), whose slope 1 / (2 sqrt(luminance)) scales the error of the mean.
+    fn error(&self) -> f64 {
+        if self.count < 2.0 {
+            return f64::INFINITY;
+        }
+        let variance = self.m2 / (self.count - 1.0);
+        let standard_error = (variance / self.count).sqrt();
+        standard_error / (2.0 * self.mean.max(1e-4).sqrt())
+    }
+}
 
 pub struct Camera {
     pub aspect_ratio: f64,
     pub image_width: i32,
+    // the number of samples for each pixel, or the most samples with adaptive sampling
     pub samples_per_pixel: i32,
+    // fewest samples for a pixel before adaptive sampling may stop
+    pub min_samples_per_pixel: i32,
+    // Adaptive sampling stops a pixel once the estimated noise in its gamma-corrected
+    // luminance drops below this (1/255 is one 8-bit step); 0 disables it.
+    pub noise_threshold: f64,
     pub max_depth: i32,
     pub vfov: f64,
+    // variation angle of rays through each pixel, in degrees
+    pub defocus_angle: f64,
+    // distance from look_from to the plane of perfect focus
+    pub focus_dist: f64,
+    // number of worker threads used by render, defaults to the available cores
+    pub threads: usize,
+    pub background: Background,
+    // every pixel's random samples are derived from this, so equal seeds give equal images
+    pub seed: u64,
+    // how the sample positions and scatter directions are chosen
+    pub sampler: SamplerKind,
+    // whether render fills in the framebuffer's depth and normals
+    pub passes: bool,
     u: Vec3,
     v: Vec3,
     w: Vec3,
@@ -18,13 +114,15 @@ pub struct Camera {
     vup: Vec3,
     image_height: i32,
     center: Point3,
-    pixel_sample_scale: f64,
     pixel00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
+    defocus_disk_u: Vec3,
+    defocus_disk_v: Vec3,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         vfov: f64,
@@ -40,9 +138,17 @@ impl Camera {
             vfov,
             image_width,
             samples_per_pixel,
+            min_samples_per_pixel: 16,
+            noise_threshold: 0.0,
             max_depth,
+            defocus_angle: 0.0,
+            focus_dist: (look_at - look_from).length(),
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            background: Background::SKY,
+            seed: 0,
+            sampler: SamplerKind::Random,
+            passes: false,
             image_height: 0,
-            pixel_sample_scale: 0.0,
             u: Vec3(0.0, 0.0, 0.0),
             v: Vec3(0.0, 0.0, 0.0),
             w: Vec3(0.0, 0.0, 0.0),
@@ -53,36 +159,156 @@ impl Camera {
             pixel00_loc: Vec3(0.0, 0.0, 0.0),
             pixel_delta_u: Vec3(0.0, 0.0, 0.0),
             pixel_delta_v: Vec3(0.0, 0.0, 0.0),
+            defocus_disk_u: Vec3(0.0, 0.0, 0.0),
+            defocus_disk_v: Vec3(0.0, 0.0, 0.0),
         }
     }
 
-    pub fn render<F: FnMut(String)>(&mut self, mut output: F, world: HittableList) {
+    // Renders the scene into a framebuffer of linear colors, depths and normals.
+    pub fn render(&mut self, world: HittableList) -> Framebuffer {
+        self.initialize();
+
+        let mut framebuffer =
+            Framebuffer::new(self.image_width as usize, self.image_height as usize);
+        let mut j = 0;
+        self.render_rows(world, |row| {
+            for (i, pixel) in row.into_iter().enumerate() {
+                let index = j * framebuffer.width() + i;
+                framebuffer.pixels_mut()[index] = pixel.color;
+                framebuffer.depth_mut()[index] = pixel.depth;
+                framebuffer.normals_mut()[index] = pixel.normal;
+                framebuffer.samples_mut()[index] = pixel.samples as u32;
+            }
+            j += 1;
+        });
+        framebuffer
+    }
+
+    // Renders the scene as ASCII PPM text, handing each line to output as soon as its row is
+    // done instead of keeping the whole image in memory.
+    pub fn render_streaming<F: FnMut(String)>(&mut self, mut output: F, world: HittableList) {
         self.initialize();
 
-        // Render
         output(format!(
             "P3\n{} {}\n255\n",
             self.image_width, self.image_height
         ));
 
-        for j in 0..(self.image_height) as i32 {
-            for i in 0..self.image_width {
-                let pixel_color = (0..self.samples_per_pixel)
-                    .map(|_| {
-                        let r = self.get_ray(i, j);
-                        self.ray_color(r, &world, self.max_depth)
-                    })
-                    .fold(Vec3(0.0, 0.0, 0.0), |acc, x| acc + x)
-                    * self.pixel_sample_scale as f64;
+        self.render_rows(world, |row| {
+            for pixel in row {
+                output(Vec3::get_color(&pixel.color));
+            }
+        });
+    }
+
+    // Height of the rendered image, as derived from image_width and aspect_ratio.
+    pub fn image_height(&self) -> i32 {
+        ((self.image_width as f64 / self.aspect_ratio) as i32).max(1)
+    }
 
-                output(Vec3::get_color(&pixel_color));
+    // Renders rows in parallel and hands them to on_row in top-to-bottom order.
+    fn render_rows<F: FnMut(Vec<Pixel>)>(&self, world: HittableList, mut on_row: F) {
+        let camera = self;
+        let world = &world.into_bvh();
+        let next_row = AtomicI32::new(0);
+        let (sender, receiver) = mpsc::channel();
+
+        thread::scope(|scope| {
+            // Each worker takes the next unrendered row until none are left.
+            for _ in 0..camera.threads.max(1) {
+                let sender = sender.clone();
+                let next_row = &next_row;
+                scope.spawn(move || loop {
+                    let j = next_row.fetch_add(1, Ordering::Relaxed);
+                    if j >= camera.image_height {
+                        break;
+                    }
+                    let row = camera.render_row(j, world);
+                    if sender.send((j, row)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(sender);
+
+            // Rows finish out of order, so hold them back until every row above is written.
+            let mut pending: Vec<Option<Vec<Pixel>>> =
+                (0..camera.image_height).map(|_| None).collect();
+            let mut next_output = 0;
+            for (j, row) in receiver {
+                pending[j as usize] = Some(row);
+                while let Some(row) = pending.get_mut(next_output).and_then(|row| row.take()) {
+                    on_row(row);
+                    next_output += 1;
+                }
             }
+        });
+    }
+
+    fn render_row(&self, j: i32, world: &HittableList) -> Vec<Pixel> {
+        (0..self.image_width)
+            .map(|i| self.sample_pixel(i, j, world))
+            .collect()
+    }
+
+    // The averaged color of pixel i, j and the number of samples it took. Without a noise
+    // threshold every pixel takes samples_per_pixel samples.
+    fn sample_pixel(&self, i: i32, j: i32, world: &HittableList) -> Pixel {
+        let adaptive = self.noise_threshold > 0.0;
+        // every pixel takes at least one sample
+        let samples_per_pixel = self.samples_per_pixel.max(1);
+        let min_samples = self.min_samples_per_pixel.max(1).min(samples_per_pixel);
+        let mut sampler = Sampler::new(self.sampler, samples_per_pixel, self.seed, i, j);
+        if adaptive {
+            // stratified grids of min_samples each, so the pixel can stop after any of them
+            sampler = sampler.with_strata(min_samples);
+        }
+        let mut stats = PixelStats::new();
+        let mut sum = Vec3(0.0, 0.0, 0.0);
+        let (mut depth, mut normal) = (f64::INFINITY, Vec3(0.0, 0.0, 0.0));
+        let mut samples = 0;
+        while samples < samples_per_pixel {
+            sampler.start_sample(samples);
+            let r = self.get_ray(i, j, &mut sampler);
+            let color = if self.passes && samples == 0 {
+                // The passes come from the first sample's ray, so they line up with the
+                // color, defocus blur included, without tracing another ray.
+                let (color, first_hit) = self.primary_ray_color(r, world, &mut sampler);
+                (depth, normal) = first_hit;
+                color
+            } else {
+                self.ray_color(r, world, self.max_depth, &mut sampler)
+            };
+            sum += color;
+            samples += 1;
+
+            if adaptive {
+                stats.add(color);
+                if samples >= min_samples
+                    && sampler.is_stratum_boundary(samples)
+                    && stats.error() < self.noise_threshold
+                {
+                    break;
+                }
+            }
+        }
+        Pixel {
+            color: sum * (1.0 / samples as f64),
+            depth,
+            normal,
+            samples,
         }
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let offset = self.sample_square();
-        let origin = self.center;
+    // Construct a camera ray originating from the defocus disk and directed at a randomly
+    // sampled point around the pixel location i, j.
+    fn get_ray(&self, i: i32, j: i32, sampler: &mut Sampler) -> Ray {
+        let offset = self.sample_square(sampler);
+        let origin = if self.defocus_angle <= 0.0 {
+            self.center
+        } else {
+            self.defocus_disk_sample(sampler)
+        };
         Ray {
             origin,
             dir: self.pixel00_loc
@@ -92,13 +318,20 @@ impl Camera {
         }
     }
 
-    fn sample_square(&self) -> Vec3 {
-        Vec3::new(random_double() - 0.5, random_double() - 0.5, 0.0)
+    fn sample_square(&self, sampler: &mut Sampler) -> Vec3 {
+        let (x, y) = sampler.next_2d();
+        Vec3::new(x - 0.5, y - 0.5, 0.0)
+    }
+
+    // Returns a random point in the camera defocus disk.
+    fn defocus_disk_sample(&self, sampler: &mut Sampler) -> Point3 {
+        let p = sampler.in_unit_disk();
+        self.center + (self.defocus_disk_u * p.x()) + (self.defocus_disk_v * p.y())
     }
 
     // if hits an object, return the color of the object
     // else return the background color
-    fn ray_color(&self, r: Ray, world: &HittableList, depth: i32) -> Color {
+    fn ray_color(&self, r: Ray, world: &HittableList, depth: i32, sampler: &mut Sampler) -> Color {
         if depth <= 0 {
             return Vec3(0.0, 0.0, 0.0);
         }
@@ -106,38 +339,66 @@ impl Camera {
         let mut rec = HitRecord::new();
 
         if world.hit(&r, Interval::new(0.0001, f64::INFINITY), &mut rec) {
-            let (ok, scattered, attenuation) =
-                rec.material
-                    .scatter(&r, rec.point, rec.normal, rec.front_face);
-            if ok {
-                return attenuation * self.ray_color(scattered, world, depth - 1);
-            }
-            return Color::new(1.0, 1.0, 1.0);
+            return self.shade(&r, &rec, world, depth, sampler);
         }
 
-        let unit_direction = r.dir.unit_vector();
-        let a = 0.5 * (unit_direction.y() + 1.0);
-        Vec3(1.0, 1.0, 1.0) * (1.0 - a) + Vec3(0.5, 0.7, 1.0) * (a)
+        self.background.color(r.dir)
     }
 
-    fn initialize(&mut self) {
-        self.center = self.look_from;
-        self.image_height = {
-            let image_height = (self.image_width as f64 / self.aspect_ratio) as i32;
-            if image_height < 1 {
-                1
+    // Like ray_color for a ray leaving the camera, also returning the depth and normal of
+    // the surface it hits, or infinity and a zero normal if it hits nothing.
+    fn primary_ray_color(
+        &self,
+        r: Ray,
+        world: &HittableList,
+        sampler: &mut Sampler,
+    ) -> (Color, (f64, Vec3)) {
+        let mut rec = HitRecord::new();
+        if !world.hit(&r, Interval::new(0.0001, f64::INFINITY), &mut rec) {
+            let color = if self.max_depth > 0 {
+                self.background.color(r.dir)
             } else {
-                image_height
-            }
-        };
-        self.pixel_sample_scale = 1.0 / self.samples_per_pixel as f64;
+                Vec3(0.0, 0.0, 0.0)
+            };
+            return (color, (f64::INFINITY, Vec3(0.0, 0.0, 0.0)));
+        }
+        let first_hit = ((rec.point - self.center).dot(-self.w), rec.normal);
+        if self.max_depth <= 0 {
+            return (Vec3(0.0, 0.0, 0.0), first_hit);
+        }
+        (
+            self.shade(&r, &rec, world, self.max_depth, sampler),
+            first_hit,
+        )
+    }
 
-        let focal_length = (self.look_at - self.look_from).length();
+    // The light leaving the surface rec along -r: what it emits plus what it scatters
+    // from a new ray, which may bounce depth - 1 more times.
+    fn shade(
+        &self,
+        r: &Ray,
+        rec: &HitRecord,
+        world: &HittableList,
+        depth: i32,
+        sampler: &mut Sampler,
+    ) -> Color {
+        let color_from_emission = rec.material.emitted();
+        let (ok, scattered, attenuation) = rec.material.scatter(r, rec, sampler);
+        if ok {
+            return color_from_emission
+                + attenuation * self.ray_color(scattered, world, depth - 1, sampler);
+        }
+        color_from_emission
+    }
+
+    fn initialize(&mut self) {
+        self.center = self.look_from;
+        self.image_height = self.image_height();
 
         let theta = self.vfov.to_radians();
         let h = (theta / 2.0).tan();
 
-        let viewport_height = h * 2.0 * focal_length;
+        let viewport_height = h * 2.0 * self.focus_dist;
         let viewport_width = viewport_height * self.image_width as f64 / self.image_height as f64;
 
         self.w = (self.look_from - self.look_at).unit_vector();
@@ -151,39 +412,303 @@ impl Camera {
         self.pixel_delta_v = viewport_v / self.image_height as f64;
 
         let upper_left_corner =
-            self.center - (self.w * focal_length) - (viewport_u / 2.0) - (viewport_v / 2.0);
+            self.center - (self.w * self.focus_dist) - (viewport_u / 2.0) - (viewport_v / 2.0);
 
         self.pixel00_loc = upper_left_corner + self.pixel_delta_u / 2.0 + self.pixel_delta_v / 2.0;
+
+        // Calculate the camera defocus disk basis vectors.
+        let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
+        self.defocus_disk_u = self.u * defocus_radius;
+        self.defocus_disk_v = self.v * defocus_radius;
     }
 }
 
-// #[cfg(test)]
-// mod tests {
-//     use super::*;
-//
-//     #[test]
-//     fn test_get_ray() {
-//         let mut outs = Vec::new();
-//         let mut camera = Camera::new(16.0 / 9.0, 16, 10, 10);
-//         let world = HittableList {
-//             objects: vec![
-//                 HittableEnum::Sphere(Sphere::new(Vec3(0.0, 0.0, -1.0), 0.5)),
-//                 HittableEnum::Sphere(Sphere::new(Vec3(0.0, -100.5, -1.0), 100.0)),
-//             ],
-//         };
-//         camera.initialize();
-//         for i in 0..camera.image_height {
-//             for j in 0..camera.image_width {
-//                 let pixel_color = (0..camera.samples_per_pixel)
-//                     .map(|_| {
-//                         let r = camera.get_ray(i, j);
-//                         camera.ray_color(r, &world, camera.max_depth)
-//                     })
-//                     .fold(Vec3(0.0, 0.0, 0.0), |acc, x| acc + x)
-//                     * camera.pixel_sample_scale;
-//                 outs.push(pixel_color);
-//             }
-//         }
-//         assert!(outs.iter().all(|x| -0.5 <= x.x() && x.x() <= 1.5));
-//     }
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::*;
+    use crate::texture::*;
+
+    #[test]
+    fn test_get_ray() {
+        let mut outs = Vec::new();
+        let mut camera = Camera::new(
+            16.0 / 9.0,
+            90.0,
+            16,
+            10,
+            10,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, 0.0),
+        );
+        let world = HittableList {
+            objects: vec![
+                HittableEnum::Sphere(Sphere::new(
+                    Vec3(0.0, 0.0, -1.0),
+                    0.5,
+                    Material::Lambertian(Texture::Solid(Vec3(0.5, 0.5, 0.5))),
+                )),
+                HittableEnum::Sphere(Sphere::new(
+                    Vec3(0.0, -100.5, -1.0),
+                    100.0,
+                    Material::Lambertian(Texture::Solid(Vec3(0.5, 0.5, 0.5))),
+                )),
+            ],
+        };
+        camera.initialize();
+        let mut sampler = Sampler::new(camera.sampler, camera.samples_per_pixel, camera.seed, 0, 0);
+        for j in 0..camera.image_height {
+            for i in 0..camera.image_width {
+                let pixel_color = (0..camera.samples_per_pixel)
+                    .map(|s| {
+                        sampler.start_sample(s);
+                        let r = camera.get_ray(i, j, &mut sampler);
+                        camera.ray_color(r, &world, camera.max_depth, &mut sampler)
+                    })
+                    .fold(Vec3(0.0, 0.0, 0.0), |acc, x| acc + x)
+                    / camera.samples_per_pixel as f64;
+                outs.push(pixel_color);
+            }
+        }
+        assert!(outs.iter().all(|x| -0.5 <= x.x() && x.x() <= 1.5));
+    }
+
+    #[test]
+    fn test_passes() {
+        let world = || HittableList {
+            objects: vec![
+                HittableEnum::Sphere(Sphere::new(
+                    Vec3(0.0, 0.0, -1.0),
+                    0.5,
+                    Material::Lambertian(Texture::Solid(Vec3(0.5, 0.5, 0.5))),
+                )),
+                HittableEnum::Sphere(Sphere::new(
+                    Vec3(0.0, -100.5, -1.0),
+                    100.0,
+                    Material::Lambertian(Texture::Solid(Vec3(0.5, 0.5, 0.5))),
+                )),
+            ],
+        };
+        let render = |passes: bool| {
+            let mut camera = Camera::new(
+                1.0,
+                90.0,
+                15,
+                8,
+                10,
+                Vec3(0.0, 0.0, 0.0),
+                Vec3(0.0, 0.0, -1.0),
+                Vec3(0.0, 1.0, 0.0),
+            );
+            camera.defocus_angle = 2.0;
+            camera.passes = passes;
+            camera.render(world())
+        };
+        let without = render(false);
+        let with = render(true);
+        assert!(without.depth().iter().all(|d| d.is_infinite()));
+
+        // the passes come for free, without changing the color
+        let xyz = |v: &Vec3| (v.x(), v.y(), v.z());
+        assert!(with
+            .pixels()
+            .iter()
+            .map(xyz)
+            .eq(without.pixels().iter().map(xyz)));
+
+        // the sphere's front is half a unit away, facing the camera
+        let center = 7 * 15 + 7;
+        assert!((with.depth()[center] - 0.5).abs() < 0.01);
+        assert!(with.normals()[center].z() > 0.99);
+        // the top row sees the sky
+        assert!(with.depth()[..15].iter().all(|d| d.is_infinite()));
+        assert!(with.normals()[..15].iter().all(|n| n.length() == 0.0));
+    }
+
+    // Every pixel draws from its own stream, so the image can't depend on how the rows
+    // are spread over the threads.
+    #[test]
+    fn test_thread_count() {
+        let render = |threads: usize| {
+            let mut camera = Camera::new(
+                1.5,
+                60.0,
+                24,
+                8,
+                10,
+                Vec3(0.0, 0.5, 1.0),
+                Vec3(0.0, 0.0, -1.0),
+                Vec3(0.0, 1.0, 0.0),
+            );
+            camera.defocus_angle = 1.0;
+            camera.noise_threshold = 0.05;
+            camera.min_samples_per_pixel = 2;
+            camera.passes = true;
+            camera.threads = threads;
+            camera.render(HittableList {
+                objects: vec![
+                    HittableEnum::Sphere(Sphere::new(
+                        Vec3(0.0, 0.0, -1.0),
+                        0.5,
+                        Material::Dielectric(1.5),
+                    )),
+                    HittableEnum::Sphere(Sphere::new(
+                        Vec3(0.0, -100.5, -1.0),
+                        100.0,
+                        Material::new_metal(Texture::Solid(Vec3(0.8, 0.6, 0.2)), 0.3),
+                    )),
+                ],
+            })
+        };
+        let single = render(1);
+        for &threads in [2, 5].iter() {
+            let multi = render(threads);
+            let bits = |v: &Vec3| (v.x().to_bits(), v.y().to_bits(), v.z().to_bits());
+            assert!(single
+                .pixels()
+                .iter()
+                .map(bits)
+                .eq(multi.pixels().iter().map(bits)));
+            assert!(single
+                .normals()
+                .iter()
+                .map(bits)
+                .eq(multi.normals().iter().map(bits)));
+            assert_eq!(single.samples(), multi.samples());
+            assert!(single
+                .depth()
+                .iter()
+                .map(|d| d.to_bits())
+                .eq(multi.depth().iter().map(|d| d.to_bits())));
+        }
+    }
+
+    // A sphere in front of a flat background, sampled adaptively. The sphere has a checker
+    // pattern much finer than a pixel, so the samples of every pixel that sees it disagree.
+    fn render_adaptive(sampler: SamplerKind, samples_per_pixel: i32) -> Framebuffer {
+        let mut camera = Camera::new(
+            1.0,
+            60.0,
+            12,
+            samples_per_pixel,
+            10,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, 0.0),
+        );
+        camera.background = Background::Solid(Vec3(0.6, 0.7, 0.9));
+        camera.noise_threshold = 0.001;
+        camera.min_samples_per_pixel = 14;
+        camera.sampler = sampler;
+        camera.render(HittableList {
+            objects: vec![HittableEnum::Sphere(Sphere::new(
+                Vec3(0.0, 0.0, -2.0),
+                0.6,
+                Material::Lambertian(Texture::checker(
+                    0.003,
+                    Vec3(0.5, 0.5, 0.5),
+                    Vec3(0.0, 0.0, 0.0),
+                )),
+            ))],
+        })
+    }
+
+    #[test]
+    fn test_adaptive_sampling() {
+        // Pixels that only see the background have no noise at all and stop as soon as
+        // they may, the others take every sample.
+        let check = |sampler: SamplerKind, flat_samples: u32| {
+            let framebuffer = render_adaptive(sampler, 48);
+            let mut counts = [0, 0];
+            for (color, &samples) in framebuffer.pixels().iter().zip(framebuffer.samples()) {
+                let flat = (*color - Vec3(0.6, 0.7, 0.9)).length() < 1e-12;
+                assert_eq!(samples, if flat { flat_samples } else { 48 });
+                counts[flat as usize] += 1;
+            }
+            assert!(counts[0] > 20 && counts[1] > 20, "{:?}", counts);
+        };
+        check(SamplerKind::Random, 14);
+        check(SamplerKind::Halton, 14);
+        // Stratified grids of 3 by 4 samples, so a pixel can only stop after 24, 36 or 48.
+        check(SamplerKind::Stratified, 24);
+    }
+
+    #[test]
+    fn test_no_samples() {
+        // too few samples per pixel still take one
+        for &samples_per_pixel in [0, -3].iter() {
+            let framebuffer = render_adaptive(SamplerKind::Random, samples_per_pixel);
+            assert!(framebuffer.samples().iter().all(|&s| s == 1));
+            assert!(framebuffer.pixels().iter().all(|p| p.x().is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_emission() {
+        let mut camera = Camera::new(
+            1.0,
+            60.0,
+            8,
+            4,
+            10,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, 0.0),
+        );
+        camera.background = Background::None;
+        camera.initialize();
+        let light = Vec3(4.0, 2.0, 1.0);
+        let world = HittableList {
+            objects: vec![HittableEnum::Sphere(Sphere::new(
+                Vec3(0.0, 0.0, -2.0),
+                0.5,
+                Material::DiffuseLight(light),
+            ))],
+        };
+        let mut sampler = Sampler::new(camera.sampler, 1, camera.seed, 0, 0);
+        sampler.start_sample(0);
+
+        // the light is all there is, so a hit sees exactly its color and a miss nothing
+        let hit = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.1, 0.0, -1.0));
+        let color = camera.ray_color(hit, &world, camera.max_depth, &mut sampler);
+        assert_eq!((color.x(), color.y(), color.z()), (4.0, 2.0, 1.0));
+        let miss = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(1.0, 0.0, -1.0));
+        let color = camera.ray_color(miss, &world, camera.max_depth, &mut sampler);
+        assert_eq!((color.x(), color.y(), color.z()), (0.0, 0.0, 0.0));
+
+        // and the render shows the light in the middle on black
+        let framebuffer = camera.render(world);
+        let center = framebuffer.pixels()[4 * 8 + 4];
+        assert_eq!((center.x(), center.y(), center.z()), (4.0, 2.0, 1.0));
+        let corner = framebuffer.pixels()[0];
+        assert_eq!((corner.x(), corner.y(), corner.z()), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_background() {
+        let rgb = |c: Color| (c.x(), c.y(), c.z());
+        let (bottom, top) = (Vec3(1.0, 0.5, 0.0), Vec3(0.0, 0.5, 1.0));
+        let gradient = Background::Gradient { bottom, top };
+        // the direction's length doesn't matter
+        assert_eq!(rgb(gradient.color(Vec3(0.0, -3.0, 0.0))), (1.0, 0.5, 0.0));
+        assert_eq!(rgb(gradient.color(Vec3(0.0, 0.2, 0.0))), (0.0, 0.5, 1.0));
+        // halfway at the horizon, a quarter of the way up at 30 degrees below it
+        assert_eq!(rgb(gradient.color(Vec3(2.0, 0.0, -1.0))), (0.5, 0.5, 0.5));
+        let below = gradient.color(Vec3(3.0f64.sqrt(), -1.0, 0.0));
+        assert!((below - Vec3(0.75, 0.5, 0.25)).length() < 1e-12);
+
+        let solid = Background::Solid(Vec3(0.1, 0.2, 0.3));
+        let none = Background::None;
+        for dir in [
+            Vec3(0.0, 1.0, 0.0),
+            Vec3(0.0, -1.0, 0.0),
+            Vec3(1.0, 0.0, 1.0),
+        ]
+        .iter()
+        {
+            assert_eq!(rgb(solid.color(*dir)), (0.1, 0.2, 0.3));
+            assert_eq!(rgb(none.color(*dir)), (0.0, 0.0, 0.0));
+        }
+    }
+}
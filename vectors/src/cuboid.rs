@@ -0,0 +1,293 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::planar::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+// The faces of a cuboid, in the order of Cuboid::with_faces: the faces towards -x, +x, -y,
+// +y, -z and +z of the box before it's rotated.
+pub const FACE_NAMES: [&str; 6] = ["left", "right", "bottom", "top", "back", "front"];
+
+const UNIT_AXES: [Vec3; 3] = [
+    Vec3(1.0, 0.0, 0.0),
+    Vec3(0.0, 1.0, 0.0),
+    Vec3(0.0, 0.0, 1.0),
+];
+
+// A rectangular box, found with a slab test in its own frame. It starts out axis-aligned
+// and can be rotated around its center, e.g. for walls, tables or pedestals. Every face
+// has texture coordinates from 0 to 1, upright when seen from outside.
+pub struct Cuboid {
+    center: Point3,
+    // half the size along each of the box's axes
+    half: [f64; 3],
+    // the box's unit axes in world space
+    axes: [Vec3; 3],
+    // boxed, so six materials don't bloat HittableEnum
+    materials: Box<[Material; 6]>,
+}
+
+impl Cuboid {
+    // The axis-aligned box with opposite corners a and b.
+    pub fn new(a: Point3, b: Point3, material: Material) -> Self {
+        let size = b - a;
+        Self {
+            center: (a + b) * 0.5,
+            half: [
+                size.x().abs() / 2.0,
+                size.y().abs() / 2.0,
+                size.z().abs() / 2.0,
+            ],
+            axes: UNIT_AXES,
+            materials: Box::new([
+                material.clone(),
+                material.clone(),
+                material.clone(),
+                material.clone(),
+                material.clone(),
+                material,
+            ]),
+        }
+    }
+
+    // One material per face, in the order of FACE_NAMES.
+    pub fn with_faces(mut self, materials: [Material; 6]) -> Self {
+        self.materials = Box::new(materials);
+        self
+    }
+
+    // Rotates the box by degrees around axis through its center, counterclockwise when
+    // looking down the axis. Rotations add up.
+    pub fn rotated(mut self, axis: Vec3, degrees: f64) -> Self {
+        let k = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        // Rodrigues' rotation formula
+        let rotate = |v: Vec3| v * cos + k.cross(v) * sin + k * (k.dot(v) * (1.0 - cos));
+        self.axes = [
+            rotate(self.axes[0]),
+            rotate(self.axes[1]),
+            rotate(self.axes[2]),
+        ];
+        self
+    }
+
+    fn to_local(&self, v: Vec3) -> [f64; 3] {
+        [
+            v.dot(self.axes[0]),
+            v.dot(self.axes[1]),
+            v.dot(self.axes[2]),
+        ]
+    }
+
+    // Texture coordinates on the face with the given local outward normal.
+    fn face_uv(&self, local: [f64; 3], normal: Vec3) -> (f64, f64) {
+        let (tangent, bitangent) = plane_basis(normal);
+        let p = Vec3(local[0], local[1], local[2]);
+        let h = Vec3(self.half[0], self.half[1], self.half[2]);
+        let coordinate = |axis: Vec3| {
+            let extent = h.dot(axis).abs();
+            if extent > 0.0 {
+                0.5 + p.dot(axis) / (2.0 * extent)
+            } else {
+                0.5
+            }
+        };
+        (coordinate(tangent), coordinate(bitangent))
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let origin = self.to_local(ray.origin - self.center);
+        let dir = self.to_local(ray.dir);
+
+        // The ray is inside all three slabs between near and far. Each end remembers
+        // the face it crosses there, as its axis and the side of the axis (0 for -, 1 for +).
+        let mut near = (f64::NEG_INFINITY, 0, 0);
+        let mut far = (f64::INFINITY, 0, 0);
+        for axis in 0..3 {
+            let h = self.half[axis];
+            if dir[axis] == 0.0 {
+                if origin[axis].abs() > h {
+                    return false;
+                }
+                continue;
+            }
+            let t0 = (-h - origin[axis]) / dir[axis];
+            let t1 = (h - origin[axis]) / dir[axis];
+            // a ray going towards + enters through the - face
+            let (enter, exit) = if dir[axis] > 0.0 {
+                ((t0, axis, 0), (t1, axis, 1))
+            } else {
+                ((t1, axis, 1), (t0, axis, 0))
+            };
+            if enter.0 > near.0 {
+                near = enter;
+            }
+            if exit.0 < far.0 {
+                far = exit;
+            }
+        }
+        if near.0 > far.0 {
+            return false;
+        }
+
+        let (t, axis, side) = if ray_t.surrounds(near.0) {
+            near
+        } else if ray_t.surrounds(far.0) {
+            far
+        } else {
+            return false;
+        };
+        let sign = if side == 1 { 1.0 } else { -1.0 };
+
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.set_face_normal(ray, self.axes[axis] * sign);
+        (rec.u, rec.v) = self.face_uv(
+            self.to_local(rec.point - self.center),
+            UNIT_AXES[axis] * sign,
+        );
+        rec.material = &self.materials[axis * 2 + side];
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // how far the box reaches from its center along a world axis
+        let reach = |world: Vec3| {
+            (0..3)
+                .map(|axis| self.axes[axis].dot(world).abs() * self.half[axis])
+                .sum::<f64>()
+        };
+        let r = Vec3(
+            reach(UNIT_AXES[0]),
+            reach(UNIT_AXES[1]),
+            reach(UNIT_AXES[2]),
+        );
+        Aabb::from_points(self.center - r, self.center + r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    // The same box built from six quads, which the slab test has to agree with. The quads
+    // don't all face outwards, so only the ray parameter and the normal facing the ray
+    // are compared.
+    #[test]
+    fn test_hit_against_quads() {
+        let cuboid = Cuboid::new(Vec3(-0.4, -0.2, -0.7), Vec3(0.6, 0.3, 0.5), Material::None)
+            .rotated(Vec3(1.0, 2.0, 0.5), 35.0)
+            .rotated(Vec3(0.0, 0.0, 1.0), -20.0);
+        let mut quads = HittableList {
+            objects: Vec::new(),
+        };
+        for axis in 0..3 {
+            let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+            let u = cuboid.axes[a] * (2.0 * cuboid.half[a]);
+            let v = cuboid.axes[b] * (2.0 * cuboid.half[b]);
+            for &sign in [-1.0, 1.0].iter() {
+                let corner = cuboid.center + cuboid.axes[axis] * (sign * cuboid.half[axis])
+                    - u * 0.5
+                    - v * 0.5;
+                quads
+                    .objects
+                    .push(HittableEnum::Quad(Quad::new(corner, u, v, Material::None)));
+            }
+        }
+
+        let mut rng = seeded_rng(3);
+        let mut hits = 0;
+        for i in 0..5000 {
+            // every other ray starts inside the box
+            let origin = if i % 2 == 0 {
+                cuboid.center + random_with_range(&mut rng, -2.0, 2.0)
+            } else {
+                cuboid.center + random_with_range(&mut rng, -0.2, 0.2)
+            };
+            let target = cuboid.center + random_with_range(&mut rng, -0.8, 0.8);
+            let ray = Ray::new(origin, target - origin);
+            let ray_t = Interval::new(0.001, f64::INFINITY);
+            let (mut expected, mut actual) = (HitRecord::new(), HitRecord::new());
+            let hit = cuboid.hit(&ray, ray_t, &mut actual);
+            assert_eq!(hit, quads.hit(&ray, ray_t, &mut expected), "ray {}", i);
+            if !hit {
+                continue;
+            }
+            hits += 1;
+            assert!((actual.t - expected.t).abs() < 1e-9, "ray {}", i);
+            assert!(
+                (actual.normal - expected.normal).length() < 1e-9,
+                "ray {}: normal {:?}, expected {:?}",
+                i,
+                actual.normal,
+                expected.normal
+            );
+        }
+        assert!(hits > 2500);
+    }
+
+    // Fires a ray from outside the box straight through point on the face with the given
+    // outward normal, returning the index of the face's material and the texture coordinates.
+    fn hit_face(cuboid: &Cuboid, point: Point3, normal: Vec3) -> (usize, f64, f64) {
+        let ray = Ray::new(point + normal * 5.0, -normal);
+        let mut rec = HitRecord::new();
+        assert!(cuboid.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        let face = match rec.material {
+            Material::Dielectric(index) => *index as usize,
+            _ => panic!("expected a face material"),
+        };
+        (face, rec.u, rec.v)
+    }
+
+    #[test]
+    fn test_faces() {
+        let faces = [0, 1, 2, 3, 4, 5].map(|i| Material::Dielectric(i as f64));
+        let cuboid = Cuboid::new(Vec3(-1.0, -1.0, -1.0), Vec3(1.0, 1.0, 1.0), Material::None)
+            .with_faces(faces);
+        // the faces in the order of FACE_NAMES, each hit at (0.5, 0.5) along its tangent
+        // and bitangent, e.g. up and to the right on the front face
+        let points = [
+            Vec3(-1.0, 0.5, 0.5),
+            Vec3(1.0, 0.5, -0.5),
+            Vec3(0.5, -1.0, 0.5),
+            Vec3(0.5, 1.0, -0.5),
+            Vec3(-0.5, 0.5, -1.0),
+            Vec3(0.5, 0.5, 1.0),
+        ];
+        for (i, &point) in points.iter().enumerate() {
+            let sign = if i % 2 == 0 { -1.0 } else { 1.0 };
+            let (face, u, v) = hit_face(&cuboid, point, UNIT_AXES[i / 2] * sign);
+            assert_eq!(face, i, "{} face", FACE_NAMES[i]);
+            assert!(
+                (u - 0.75).abs() < 1e-12 && (v - 0.75).abs() < 1e-12,
+                "{} face at ({}, {})",
+                FACE_NAMES[i],
+                u,
+                v
+            );
+        }
+
+        // turned a quarter to the left, the front faces +x and the right face -z
+        let faces = [0, 1, 2, 3, 4, 5].map(|i| Material::Dielectric(i as f64));
+        let turned = Cuboid::new(Vec3(-1.0, -1.0, -1.0), Vec3(1.0, 1.0, 1.0), Material::None)
+            .with_faces(faces)
+            .rotated(Vec3(0.0, 1.0, 0.0), 90.0);
+        assert_eq!(
+            hit_face(&turned, Vec3(1.0, 0.5, 0.5), Vec3(1.0, 0.0, 0.0)).0,
+            5
+        );
+        assert_eq!(
+            hit_face(&turned, Vec3(0.5, 0.5, -1.0), Vec3(0.0, 0.0, -1.0)).0,
+            1
+        );
+        assert_eq!(
+            hit_face(&turned, Vec3(0.5, 1.0, 0.5), Vec3(0.0, 1.0, 0.0)).0,
+            3
+        );
+    }
+}
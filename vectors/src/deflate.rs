@@ -0,0 +1,627 @@
+// A small zlib (RFC 1950) / DEFLATE (RFC 1951) implementation for the image formats.
+// The compressor finds matches with hash chains over a 32KiB window and writes them with
+// the fixed Huffman codes, so its output is a single compressed block. The decompressor
+// handles every block type, so it can read streams written by other encoders.
+
+use std::fmt;
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+const HASH_BITS: u32 = 15;
+
+// base length and number of extra bits for length codes 257..=285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// base distance and number of extra bits for distance codes 0..=29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+// Packs bits least significant first, as DEFLATE expects.
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        Self {
+            bytes: Vec::new(),
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.count;
+        self.count += bits;
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    // Huffman codes are defined most significant bit first, so they are written reversed.
+    fn write_code(&mut self, code: u32, bits: u32) {
+        let reversed = code.reverse_bits() >> (32 - bits);
+        self.write_bits(reversed, bits);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+fn write_literal(out: &mut BitWriter, symbol: u16) {
+    let symbol = symbol as u32;
+    match symbol {
+        0..=143 => out.write_code(0x30 + symbol, 8),
+        144..=255 => out.write_code(0x190 + symbol - 144, 9),
+        256..=279 => out.write_code(symbol - 256, 7),
+        _ => out.write_code(0xc0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(out: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASE
+        .iter()
+        .rposition(|&base| base as usize <= length)
+        .unwrap();
+    write_literal(out, 257 + code as u16);
+    out.write_bits(
+        (length - LENGTH_BASE[code] as usize) as u32,
+        LENGTH_EXTRA[code] as u32,
+    );
+
+    let code = DIST_BASE
+        .iter()
+        .rposition(|&base| base as usize <= distance)
+        .unwrap();
+    out.write_code(code as u32, 5);
+    out.write_bits(
+        (distance - DIST_BASE[code] as usize) as u32,
+        DIST_EXTRA[code] as u32,
+    );
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    let v = (data[i] as u32) << 16 | (data[i + 1] as u32) << 8 | data[i + 2] as u32;
+    (v.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+// Compresses data into a raw DEFLATE stream.
+pub fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter::new();
+    // BFINAL = 1, BTYPE = 01 (fixed Huffman codes)
+    out.write_bits(1, 1);
+    out.write_bits(1, 2);
+
+    // head[h] is the latest position + 1 with hash h, prev links back through older ones
+    let mut head = vec![0usize; 1 << HASH_BITS];
+    let mut prev = vec![0usize; WINDOW_SIZE];
+    let insert = |head: &mut Vec<usize>, prev: &mut Vec<usize>, i: usize| {
+        if i + MIN_MATCH <= data.len() {
+            let h = hash(data, i);
+            prev[i % WINDOW_SIZE] = head[h];
+            head[h] = i + 1;
+        }
+    };
+
+    let mut i = 0;
+    while i < data.len() {
+        let mut best_length = 0;
+        let mut best_distance = 0;
+
+        if i + MIN_MATCH <= data.len() {
+            let max_length = MAX_MATCH.min(data.len() - i);
+            let mut candidate = head[hash(data, i)];
+            let mut chain = 0;
+            while candidate > 0 && chain < MAX_CHAIN {
+                let j = candidate - 1;
+                if i - j > WINDOW_SIZE - 1 {
+                    break;
+                }
+                let length = data[j..]
+                    .iter()
+                    .zip(&data[i..i + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if length > best_length {
+                    best_length = length;
+                    best_distance = i - j;
+                    if length == max_length {
+                        break;
+                    }
+                }
+                let next = prev[j % WINDOW_SIZE];
+                // stale entries from an older pass over the window point forward
+                if next >= candidate {
+                    break;
+                }
+                candidate = next;
+                chain += 1;
+            }
+        }
+
+        if best_length >= MIN_MATCH {
+            write_match(&mut out, best_length, best_distance);
+            for k in i..i + best_length {
+                insert(&mut head, &mut prev, k);
+            }
+            i += best_length;
+        } else {
+            write_literal(&mut out, data[i] as u16);
+            insert(&mut head, &mut prev, i);
+            i += 1;
+        }
+    }
+
+    write_literal(&mut out, 256);
+    out.finish()
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    // 5552 is the largest block that can't overflow b before taking the modulus
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+// Compresses data into a zlib stream: header, DEFLATE data and Adler-32 checksum.
+pub fn zlib_compress(data: &[u8]) -> Vec<u8> {
+    // CM = 8 (deflate), CINFO = 7 (32K window), FLEVEL = 2 (default), FCHECK makes it % 31 == 0
+    let mut out = vec![0x78, 0x9c];
+    out.extend(deflate(data));
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+#[derive(Debug)]
+pub struct InflateError(&'static str);
+
+impl fmt::Display for InflateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid compressed data: {}", self.0)
+    }
+}
+
+impl std::error::Error for InflateError {}
+
+// Reads bits least significant first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, bits: u32) -> Result<u32, InflateError> {
+        while self.count < bits {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(InflateError("unexpected end of data"))?;
+            self.position += 1;
+            self.buffer |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let value = self.buffer & ((1u64 << bits) - 1) as u32;
+        self.buffer >>= bits;
+        self.count -= bits;
+        Ok(value)
+    }
+
+    // Drops the bits left in the current byte, for stored blocks.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+// A canonical Huffman code, stored as the number of codes of each length and the
+// symbols ordered by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // A code can't have more codes of a length than that length leaves room for.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(InflateError("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+
+    fn decode(&self, input: &mut BitReader) -> Result<u16, InflateError> {
+        // Codes of each length follow directly after the codes one bit shorter.
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= input.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError("invalid Huffman code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    // the fixed codes are complete, so they can't fail to build
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&[5; 30]).unwrap(),
+    )
+}
+
+fn dynamic_codes(input: &mut BitReader) -> Result<(Huffman, Huffman), InflateError> {
+    // order in which the code length code lengths are stored
+    const ORDER: [usize; 19] = [
+        16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+    ];
+
+    let literal_count = input.bits(5)? as usize + 257;
+    let distance_count = input.bits(5)? as usize + 1;
+    let code_length_count = input.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(InflateError("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &ORDER[..code_length_count] {
+        code_lengths[index] = input.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_length_code.decode(input)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err(InflateError("repeated length with no previous length"));
+                }
+                (lengths[i - 1], 3 + input.bits(2)? as usize)
+            }
+            17 => (0, 3 + input.bits(3)? as usize),
+            _ => (0, 11 + input.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(InflateError("too many code lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(InflateError("no end of block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+fn inflate_block(
+    input: &mut BitReader,
+    out: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), InflateError> {
+    let too_large = InflateError("decompressed data is too large");
+    loop {
+        let symbol = literals.decode(input)? as usize;
+        match symbol {
+            0..=255 => {
+                if out.len() >= limit {
+                    return Err(too_large);
+                }
+                out.push(symbol as u8)
+            }
+            256 => return Ok(()),
+            _ => {
+                let code = symbol - 257;
+                if code >= LENGTH_BASE.len() {
+                    return Err(InflateError("invalid length code"));
+                }
+                let length =
+                    LENGTH_BASE[code] as usize + input.bits(LENGTH_EXTRA[code] as u32)? as usize;
+
+                let code = distances.decode(input)? as usize;
+                if code >= DIST_BASE.len() {
+                    return Err(InflateError("invalid distance code"));
+                }
+                let distance =
+                    DIST_BASE[code] as usize + input.bits(DIST_EXTRA[code] as u32)? as usize;
+                if distance > out.len() {
+                    return Err(InflateError("distance too far back"));
+                }
+                if length > limit - out.len() {
+                    return Err(too_large);
+                }
+
+                // The match may overlap the bytes it produces, so copy one byte at a time.
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+        }
+    }
+}
+
+// Decompresses a raw DEFLATE stream, returning the data and the number of bytes read. Fails
+// rather than producing more than limit bytes, so a tiny stream can't exhaust memory.
+fn inflate_stream(data: &[u8], limit: usize) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut input = BitReader::new(data);
+    let mut out = Vec::new();
+    loop {
+        let last = input.bits(1)? == 1;
+        match input.bits(2)? {
+            0 => {
+                input.align();
+                let position = input.position;
+                let header = data
+                    .get(position..position + 4)
+                    .ok_or(InflateError("unexpected end of data"))?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if length != !complement {
+                    return Err(InflateError(
+                        "stored block length doesn't match its complement",
+                    ));
+                }
+                let start = position + 4;
+                let stored = data
+                    .get(start..start + length as usize)
+                    .ok_or(InflateError("unexpected end of data"))?;
+                if stored.len() > limit - out.len() {
+                    return Err(InflateError("decompressed data is too large"));
+                }
+                out.extend_from_slice(stored);
+                input.position = start + length as usize;
+            }
+            1 => {
+                let (literals, distances) = fixed_codes();
+                inflate_block(&mut input, &mut out, limit, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut input)?;
+                inflate_block(&mut input, &mut out, limit, &literals, &distances)?;
+            }
+            _ => return Err(InflateError("invalid block type")),
+        }
+        if last {
+            return Ok((out, input.position));
+        }
+    }
+}
+
+// Decompresses a raw DEFLATE stream of at most limit bytes.
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, InflateError> {
+    inflate_stream(data, limit).map(|(out, _)| out)
+}
+
+// Decompresses a zlib stream of at most limit bytes and checks its Adler-32 checksum.
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 6 {
+        return Err(InflateError("unexpected end of data"));
+    }
+    if data[0] & 0x0f != 8 || u16::from_be_bytes([data[0], data[1]]) % 31 != 0 {
+        return Err(InflateError("invalid zlib header"));
+    }
+    if data[1] & 0x20 != 0 {
+        return Err(InflateError("preset dictionaries are not supported"));
+    }
+
+    let (out, length) = inflate_stream(&data[2..], limit)?;
+    let checksum = data
+        .get(2 + length..2 + length + 4)
+        .ok_or(InflateError("missing checksum"))?;
+    if u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]) != adler32(&out) {
+        return Err(InflateError("checksum mismatch"));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    // Deterministic bytes with no repetitions for the compressor to find.
+    fn noise(length: usize) -> Vec<u8> {
+        let mut rng = seeded_rng(1);
+        (0..length)
+            .map(|_| random_index(&mut rng, 256) as u8)
+            .collect()
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"a"), 0x00620062);
+        assert_eq!(adler32(b"abc"), 0x024d0127);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        // long enough for the sums to be reduced several times
+        assert_eq!(adler32(&[0xff; 100_000]), 0x149a302c);
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        let mut repeated_text = Vec::new();
+        for i in 0..2000 {
+            repeated_text.extend(format!("line {} of some text, ", i % 37).bytes());
+        }
+        // a long run of matches at the largest distance
+        let mut far_repeat = noise(WINDOW_SIZE - 1);
+        far_repeat.extend(far_repeat[..5000].to_vec());
+        let inputs: Vec<Vec<u8>> = vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"ab".to_vec(),
+            b"abcabcabc".to_vec(),
+            b"hello, world".to_vec(),
+            vec![0; 1],
+            vec![0; 100_000],
+            vec![7; MAX_MATCH + 1],
+            repeated_text,
+            noise(1000),
+            noise(3 * WINDOW_SIZE),
+            far_repeat,
+        ];
+        for data in inputs.iter() {
+            let compressed = deflate(data);
+            // exactly enough room for the data
+            let limit = data.len();
+            assert_eq!(
+                &inflate(&compressed, limit).unwrap(),
+                data,
+                "{} bytes",
+                data.len()
+            );
+            assert_eq!(
+                &zlib_decompress(&zlib_compress(data), limit).unwrap(),
+                data,
+                "{} bytes",
+                data.len()
+            );
+        }
+        // repetitions compress well
+        assert!(deflate(&[0; 100_000]).len() < 1000);
+    }
+
+    #[test]
+    fn test_invalid_streams() {
+        let data = noise(300);
+        let compressed = deflate(&data);
+        for length in 0..compressed.len() {
+            assert!(
+                inflate(&compressed[..length], usize::MAX).is_err(),
+                "{} bytes",
+                length
+            );
+        }
+        let zlib = zlib_compress(&data);
+        for length in 0..zlib.len() {
+            assert!(
+                zlib_decompress(&zlib[..length], usize::MAX).is_err(),
+                "{} bytes",
+                length
+            );
+        }
+
+        // a corrupted bit is caught by the checksum if not before (the lowest bit of a
+        // byte is read first, so it is never padding)
+        for i in 2..zlib.len() {
+            let mut corrupt = zlib.clone();
+            corrupt[i] ^= 0x01;
+            assert!(zlib_decompress(&corrupt, usize::MAX).is_err(), "byte {}", i);
+        }
+        let mut bad_header = zlib.clone();
+        bad_header[1] ^= 1;
+        assert!(zlib_decompress(&bad_header, usize::MAX).is_err());
+
+        // block type 3 doesn't exist
+        assert!(inflate(&[0x07], usize::MAX).is_err());
+        // a stored block whose length doesn't match its complement
+        assert!(inflate(&[0x01, 0x05, 0x00, 0x00, 0x00], usize::MAX).is_err());
+        // a fixed block starting with a match of distance 1
+        let mut out = BitWriter::new();
+        out.write_bits(1, 1);
+        out.write_bits(1, 2);
+        write_match(&mut out, 3, 1);
+        assert!(inflate(&out.finish(), usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_limit() {
+        let too_large = |result: Result<Vec<u8>, InflateError>| match result {
+            Err(InflateError(message)) => message == "decompressed data is too large",
+            Ok(_) => false,
+        };
+        // literals
+        let data = noise(1000);
+        assert!(too_large(inflate(&deflate(&data), 999)));
+        assert!(too_large(zlib_decompress(&zlib_compress(&data), 999)));
+        // a long match, which a few bytes of input could turn into gigabytes
+        let zeros = deflate(&[0; 100_000]);
+        assert!(too_large(inflate(&zeros, 1000)));
+        assert!(too_large(inflate(&zeros, 99_999)));
+        // a stored block of "abc"
+        let stored = [0x01, 0x03, 0x00, 0xfc, 0xff, b'a', b'b', b'c'];
+        assert_eq!(inflate(&stored, 3).unwrap(), b"abc");
+        assert!(too_large(inflate(&stored, 2)));
+    }
+}
@@ -0,0 +1,530 @@
+use crate::deflate::*;
+use crate::framebuffer::*;
+use crate::image::*;
+use crate::vec3::*;
+use std::io::{self, Write};
+
+// OpenEXR encoding of single-part scanline images with 32-bit float channels.
+// Channels are named "layer.channel" (e.g. "normal.X"); the beauty pass is the unnamed
+// default layer with plain R, G and B channels.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExrCompression {
+    None,
+    // run-length encoding, one scanline per block
+    Rle,
+    // zlib, one scanline per block
+    Zips,
+    // zlib, 16 scanlines per block
+    Zip,
+}
+
+impl ExrCompression {
+    pub const NAMES: [&'static str; 4] = ["none", "rle", "zips", "zip"];
+
+    pub fn from_name(name: &str) -> Option<ExrCompression> {
+        match name {
+            "none" => Some(ExrCompression::None),
+            "rle" => Some(ExrCompression::Rle),
+            "zips" => Some(ExrCompression::Zips),
+            "zip" => Some(ExrCompression::Zip),
+            _ => None,
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            ExrCompression::None => 0,
+            ExrCompression::Rle => 1,
+            ExrCompression::Zips => 2,
+            ExrCompression::Zip => 3,
+        }
+    }
+
+    fn lines_per_block(&self) -> usize {
+        match self {
+            ExrCompression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+pub struct ExrChannel {
+    pub name: String,
+    // one value per pixel, row by row from the top
+    pub data: Vec<f32>,
+}
+
+impl ExrChannel {
+    pub fn new<S: Into<String>>(name: S, data: Vec<f32>) -> Self {
+        Self {
+            name: name.into(),
+            data,
+        }
+    }
+
+    // The three channels of a color or vector pass, named layer.R/G/B or R/G/B for the
+    // default layer. component_names gives the suffixes, e.g. ["X", "Y", "Z"] for normals.
+    pub fn from_vectors(layer: &str, component_names: [&str; 3], values: &[Vec3]) -> Vec<Self> {
+        let name = |component: &str| {
+            if layer.is_empty() {
+                component.to_string()
+            } else {
+                format!("{}.{}", layer, component)
+            }
+        };
+        vec![
+            Self::new(
+                name(component_names[0]),
+                values.iter().map(|v| v.x() as f32).collect(),
+            ),
+            Self::new(
+                name(component_names[1]),
+                values.iter().map(|v| v.y() as f32).collect(),
+            ),
+            Self::new(
+                name(component_names[2]),
+                values.iter().map(|v| v.z() as f32).collect(),
+            ),
+        ]
+    }
+}
+
+pub struct ExrWriter {
+    pub compression: ExrCompression,
+    // also write the depth.Z and normal.X/Y/Z layers next to the beauty pass
+    pub passes: bool,
+}
+
+impl ExrWriter {
+    pub fn new(compression: ExrCompression) -> Self {
+        Self {
+            compression,
+            passes: false,
+        }
+    }
+
+    pub fn write_channels(
+        &self,
+        out: &mut dyn Write,
+        width: usize,
+        height: usize,
+        mut channels: Vec<ExrChannel>,
+    ) -> io::Result<()> {
+        for channel in channels.iter() {
+            if channel.data.len() != width * height {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("channel {} doesn't match the image size", channel.name),
+                ));
+            }
+        }
+        // The channel list, and the data in every block, must be sorted by name.
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let header = self.header(width, height, &channels);
+        let lines_per_block = self.compression.lines_per_block();
+        let blocks: Vec<Vec<u8>> = (0..height)
+            .step_by(lines_per_block)
+            .map(|y| {
+                let lines = y..(y + lines_per_block).min(height);
+                self.block(y, lines, width, &channels)
+            })
+            .collect();
+
+        // The offset table points at each block from the start of the file.
+        let mut offset = (4 + 4 + header.len() + blocks.len() * 8) as u64;
+        let mut offsets = Vec::with_capacity(blocks.len() * 8);
+        for block in blocks.iter() {
+            offsets.extend_from_slice(&offset.to_le_bytes());
+            offset += block.len() as u64;
+        }
+
+        out.write_all(&[0x76, 0x2f, 0x31, 0x01])?;
+        // version 2, single-part scanline file
+        out.write_all(&2u32.to_le_bytes())?;
+        out.write_all(&header)?;
+        out.write_all(&offsets)?;
+        for block in blocks {
+            out.write_all(&block)?;
+        }
+        Ok(())
+    }
+
+    fn header(&self, width: usize, height: usize, channels: &[ExrChannel]) -> Vec<u8> {
+        fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+            header.extend_from_slice(name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(kind.as_bytes());
+            header.push(0);
+            header.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            header.extend_from_slice(value);
+        }
+
+        let mut chlist = Vec::new();
+        for channel in channels {
+            chlist.extend_from_slice(channel.name.as_bytes());
+            chlist.push(0);
+            // pixel type FLOAT, pLinear 0 plus three reserved bytes, x and y sampling 1
+            chlist.extend_from_slice(&2i32.to_le_bytes());
+            chlist.extend_from_slice(&[0, 0, 0, 0]);
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+            chlist.extend_from_slice(&1i32.to_le_bytes());
+        }
+        chlist.push(0);
+
+        let mut window = Vec::new();
+        for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+            window.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let mut header = Vec::new();
+        attribute(&mut header, "channels", "chlist", &chlist);
+        attribute(
+            &mut header,
+            "compression",
+            "compression",
+            &[self.compression.id()],
+        );
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+        // increasing y
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1.0f32.to_le_bytes(),
+        );
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1.0f32.to_le_bytes(),
+        );
+        header.push(0);
+        header
+    }
+
+    fn block(
+        &self,
+        y: usize,
+        lines: std::ops::Range<usize>,
+        width: usize,
+        channels: &[ExrChannel],
+    ) -> Vec<u8> {
+        // Within a block each scanline holds every channel's values in turn.
+        let mut raw = Vec::new();
+        for line in lines {
+            for channel in channels {
+                for value in &channel.data[line * width..(line + 1) * width] {
+                    raw.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+
+        let compressed = match self.compression {
+            ExrCompression::None => None,
+            ExrCompression::Rle => Some(rle_compress(&predict(&raw))),
+            ExrCompression::Zips | ExrCompression::Zip => Some(zlib_compress(&predict(&raw))),
+        };
+        // Blocks that don't get smaller are stored uncompressed, readers detect this by size.
+        let data = match compressed {
+            Some(compressed) if compressed.len() < raw.len() => compressed,
+            _ => raw,
+        };
+
+        let mut block = Vec::with_capacity(data.len() + 8);
+        block.extend_from_slice(&(y as i32).to_le_bytes());
+        block.extend_from_slice(&(data.len() as i32).to_le_bytes());
+        block.extend_from_slice(&data);
+        block
+    }
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()> {
+        let mut channels = ExrChannel::from_vectors("", ["R", "G", "B"], framebuffer.pixels());
+        if self.passes {
+            channels.push(ExrChannel::new(
+                "depth.Z",
+                framebuffer.depth().iter().map(|&d| d as f32).collect(),
+            ));
+            channels.extend(ExrChannel::from_vectors(
+                "normal",
+                ["X", "Y", "Z"],
+                framebuffer.normals(),
+            ));
+        }
+        self.write_channels(out, framebuffer.width(), framebuffer.height(), channels)
+    }
+}
+
+// The preprocessing shared by RLE and ZIP compression: bytes are split into two halves
+// (even and odd positions) and then delta encoded.
+fn predict(raw: &[u8]) -> Vec<u8> {
+    let half = raw.len().div_ceil(2);
+    let mut interleaved = vec![0u8; raw.len()];
+    for (i, &byte) in raw.iter().enumerate() {
+        let position = if i % 2 == 0 { i / 2 } else { half + i / 2 };
+        interleaved[position] = byte;
+    }
+
+    let mut predicted = interleaved.clone();
+    for i in 1..interleaved.len() {
+        predicted[i] = interleaved[i]
+            .wrapping_sub(interleaved[i - 1])
+            .wrapping_add(128);
+    }
+    predicted
+}
+
+// OpenEXR's run-length encoding: a non-negative count n is followed by one byte repeated
+// n + 1 times, a negative count -n by n literal bytes.
+fn rle_compress(data: &[u8]) -> Vec<u8> {
+    const MIN_RUN_LENGTH: usize = 3;
+    const MAX_RUN_LENGTH: usize = 127;
+
+    let mut out = Vec::new();
+    let mut start = 0;
+    while start < data.len() {
+        let mut end = start + 1;
+        while end < data.len() && data[end] == data[start] && end - start - 1 < MAX_RUN_LENGTH {
+            end += 1;
+        }
+
+        if end - start >= MIN_RUN_LENGTH {
+            out.push((end - start - 1) as u8);
+            out.push(data[start]);
+        } else {
+            // Extend the literal run until the next three identical bytes.
+            while end < data.len()
+                && (end + 2 >= data.len()
+                    || data[end] != data[end + 1]
+                    || data[end + 1] != data[end + 2])
+                && end - start < MAX_RUN_LENGTH
+            {
+                end += 1;
+            }
+            out.push((-((end - start) as i32)) as u8);
+            out.extend_from_slice(&data[start..end]);
+        }
+        start = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    // The inverse of rle_compress.
+    fn rle_decompress(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            let count = data[i] as i8;
+            if count >= 0 {
+                out.extend(std::iter::repeat_n(data[i + 1], count as usize + 1));
+                i += 2;
+            } else {
+                let length = -(count as i32) as usize;
+                out.extend_from_slice(&data[i + 1..i + 1 + length]);
+                i += 1 + length;
+            }
+        }
+        out
+    }
+
+    // The inverse of predict.
+    fn unpredict(predicted: &[u8]) -> Vec<u8> {
+        let mut interleaved = predicted.to_vec();
+        for i in 1..interleaved.len() {
+            interleaved[i] = interleaved[i - 1]
+                .wrapping_add(predicted[i])
+                .wrapping_sub(128);
+        }
+        let half = interleaved.len().div_ceil(2);
+        (0..interleaved.len())
+            .map(|i| {
+                if i % 2 == 0 {
+                    interleaved[i / 2]
+                } else {
+                    interleaved[half + i / 2]
+                }
+            })
+            .collect()
+    }
+
+    fn read_u32(data: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+    }
+
+    // The header attributes as (name, type, value), and where the header ends.
+    fn read_header(data: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+        let string = |at: usize| {
+            let end = at + data[at..].iter().position(|&b| b == 0).unwrap();
+            (String::from_utf8(data[at..end].to_vec()).unwrap(), end + 1)
+        };
+        let mut attributes = Vec::new();
+        let mut at = 8;
+        while data[at] != 0 {
+            let (name, next) = string(at);
+            let (kind, next) = string(next);
+            let size = read_u32(data, next) as usize;
+            let value = data[next + 4..next + 4 + size].to_vec();
+            attributes.push((name, kind, value));
+            at = next + 4 + size;
+        }
+        (attributes, at + 1)
+    }
+
+    #[test]
+    fn test_layout() {
+        let (width, height) = (5, 37);
+        let channels = || {
+            vec![
+                // a smooth ramp, which compresses, and noise, which doesn't
+                ExrChannel::new("G", (0..width * height).map(|i| (i / 7) as f32).collect()),
+                ExrChannel::new(
+                    "A",
+                    (0..width * height)
+                        .map(|i| ((i * 2654435761) % 1000) as f32 * 0.37)
+                        .collect(),
+                ),
+            ]
+        };
+
+        for &compression in [
+            ExrCompression::None,
+            ExrCompression::Rle,
+            ExrCompression::Zips,
+            ExrCompression::Zip,
+        ]
+        .iter()
+        {
+            let mut data = Vec::new();
+            ExrWriter::new(compression)
+                .write_channels(&mut data, width, height, channels())
+                .unwrap();
+
+            assert_eq!(&data[..4], &[0x76, 0x2f, 0x31, 0x01]);
+            assert_eq!(read_u32(&data, 4), 2);
+            let (attributes, end) = read_header(&data);
+            let attribute = |name: &str| {
+                attributes
+                    .iter()
+                    .find(|(n, _, _)| n == name)
+                    .map(|(_, kind, value)| (kind.as_str(), value.as_slice()))
+                    .unwrap()
+            };
+            // the channels are sorted by name
+            let (kind, chlist) = attribute("channels");
+            assert_eq!(kind, "chlist");
+            assert_eq!(chlist.len(), 2 * (2 + 16) + 1);
+            assert_eq!(&chlist[..2], b"A\0");
+            assert_eq!(&chlist[18..20], b"G\0");
+            assert_eq!(
+                attribute("compression"),
+                ("compression", &[compression.id()][..])
+            );
+            let (kind, window) = attribute("dataWindow");
+            assert_eq!(kind, "box2i");
+            assert_eq!(
+                (read_u32(window, 8), read_u32(window, 12)),
+                (width as u32 - 1, height as u32 - 1)
+            );
+            for name in ["displayWindow", "lineOrder", "pixelAspectRatio"].iter() {
+                attribute(name);
+            }
+
+            // One offset per block, each pointing at a block that starts at the right
+            // scanline and holds the compressed lines.
+            let lines_per_block = compression.lines_per_block();
+            let blocks = height.div_ceil(lines_per_block);
+            let expected = channels();
+            let mut next_block = end + blocks * 8;
+            for block in 0..blocks {
+                let offset = u64::from_le_bytes(data[end + block * 8..][..8].try_into().unwrap());
+                assert_eq!(offset as usize, next_block);
+                let y = block * lines_per_block;
+                assert_eq!(read_u32(&data, next_block) as usize, y);
+                let size = read_u32(&data, next_block + 4) as usize;
+                let stored = &data[next_block + 8..next_block + 8 + size];
+                next_block += 8 + size;
+
+                let lines = y..(y + lines_per_block).min(height);
+                let raw_size = lines.len() * width * 4 * 2;
+                let raw = if size == raw_size {
+                    stored.to_vec()
+                } else {
+                    match compression {
+                        ExrCompression::Rle => unpredict(&rle_decompress(stored)),
+                        ExrCompression::Zips | ExrCompression::Zip => {
+                            unpredict(&zlib_decompress(stored, raw_size).unwrap())
+                        }
+                        ExrCompression::None => panic!("uncompressed block of {} bytes", size),
+                    }
+                };
+                assert_eq!(raw.len(), raw_size);
+
+                // each scanline holds all of A, then all of G
+                let mut values = raw
+                    .chunks(4)
+                    .map(|b| f32::from_le_bytes(b.try_into().unwrap()));
+                for line in lines {
+                    for channel in [&expected[1], &expected[0]] {
+                        for x in 0..width {
+                            assert_eq!(values.next(), Some(channel.data[line * width + x]));
+                        }
+                    }
+                }
+            }
+            assert_eq!(next_block, data.len());
+        }
+
+        // the smooth channel alone compresses
+        for &compression in [ExrCompression::Rle, ExrCompression::Zip].iter() {
+            let (mut compressed, mut raw) = (Vec::new(), Vec::new());
+            let smooth = |_| vec![channels().remove(0)];
+            ExrWriter::new(compression)
+                .write_channels(&mut compressed, width, height, smooth(()))
+                .unwrap();
+            ExrWriter::new(ExrCompression::None)
+                .write_channels(&mut raw, width, height, smooth(()))
+                .unwrap();
+            assert!(compressed.len() < raw.len());
+        }
+    }
+
+    #[test]
+    fn test_passes() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.depth_mut()[0] = 2.5;
+        let channel_names = |writer: &ExrWriter| {
+            let mut data = Vec::new();
+            writer.write(&mut data, &framebuffer).unwrap();
+            let (attributes, _) = read_header(&data);
+            let chlist = &attributes.iter().find(|a| a.0 == "channels").unwrap().2;
+            // each name is followed by 16 bytes of type and sampling
+            let mut names = Vec::new();
+            let mut at = 0;
+            while chlist[at] != 0 {
+                let end = at + chlist[at..].iter().position(|&b| b == 0).unwrap();
+                names.push(String::from_utf8(chlist[at..end].to_vec()).unwrap());
+                at = end + 1 + 16;
+            }
+            names
+        };
+
+        let mut writer = ExrWriter::new(ExrCompression::None);
+        assert_eq!(channel_names(&writer), ["B", "G", "R"]);
+        writer.passes = true;
+        assert_eq!(
+            channel_names(&writer),
+            ["B", "G", "R", "depth.Z", "normal.X", "normal.Y", "normal.Z"]
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use crate::vec3::*;
+
+// An in-memory image of linear colors, row by row from the top, together with
+// the depth and normal of the first surface seen through each pixel and the number
+// of samples that were averaged for it.
+#[derive(Debug, Clone)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    depth: Vec<f64>,
+    normals: Vec<Vec3>,
+    samples: Vec<u32>,
+}
+
+impl Framebuffer {
+    // A black image with infinite depth, zero normals and no samples.
+    pub fn new(width: usize, height: usize) -> Self {
+        let size = width * height;
+        Self {
+            width,
+            height,
+            pixels: vec![Vec3(0.0, 0.0, 0.0); size],
+            depth: vec![f64::INFINITY; size],
+            normals: vec![Vec3(0.0, 0.0, 0.0); size],
+            samples: vec![0; size],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({}, {}) is outside the {}x{} framebuffer",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        y * self.width + x
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        let i = self.index(x, y);
+        self.pixels[i] = color;
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Color] {
+        &mut self.pixels
+    }
+
+    // distance from the camera along its viewing direction, infinite where nothing was hit
+    pub fn depth(&self) -> &[f64] {
+        &self.depth
+    }
+
+    pub fn depth_mut(&mut self) -> &mut [f64] {
+        &mut self.depth
+    }
+
+    // world space normals facing the camera, zero where nothing was hit
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn normals_mut(&mut self) -> &mut [Vec3] {
+        &mut self.normals
+    }
+
+    pub fn samples(&self) -> &[u32] {
+        &self.samples
+    }
+
+    pub fn samples_mut(&mut self) -> &mut [u32] {
+        &mut self.samples
+    }
+
+    // An image of the sample counts, ramping from black through red and yellow to white
+    // at the largest count, for checking where adaptive sampling spent its samples.
+    pub fn sample_heatmap(&self) -> Framebuffer {
+        let max = self.samples.iter().copied().max().unwrap_or(0).max(1) as f64;
+        let mut heatmap = Framebuffer::new(self.width, self.height);
+        for (pixel, &count) in heatmap.pixels.iter_mut().zip(self.samples.iter()) {
+            let t = count as f64 / max;
+            // squared so the gamma correction of the image writers leaves the ramp linear
+            let channel = |offset: f64| (3.0 * t - offset).clamp(0.0, 1.0).powi(2);
+            *pixel = Vec3(channel(0.0), channel(1.0), channel(2.0));
+        }
+        heatmap
+    }
+
+    // Applies f to every color, e.g. to scale exposure or tonemap before encoding.
+    pub fn map_pixels<F: FnMut(Color) -> Color>(&mut self, mut f: F) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = f(*pixel);
+        }
+    }
+
+    // Gamma corrected 8-bit RGB bytes, three per pixel.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|pixel| pixel.to_bytes())
+            .collect()
+    }
+
+    // Rows of pixels from the top of the image.
+    pub fn rows(&self) -> std::slice::Chunks<'_, Color> {
+        self.pixels.chunks(self.width.max(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb(c: Color) -> (f64, f64, f64) {
+        (c.x(), c.y(), c.z())
+    }
+
+    #[test]
+    fn test_to_rgb8() {
+        let mut framebuffer = Framebuffer::new(3, 1);
+        framebuffer.set(0, 0, Vec3(0.0, 0.25, 1.0));
+        // out of range values are clamped, and NaN is black
+        framebuffer.set(1, 0, Vec3(4.0, -1.0, f64::INFINITY));
+        framebuffer.set(2, 0, Vec3(f64::NAN, 0.01, 0.5));
+        assert_eq!(
+            framebuffer.to_rgb8(),
+            vec![0, 127, 255, 255, 0, 255, 0, 25, 181]
+        );
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        assert_eq!((framebuffer.width(), framebuffer.height()), (3, 2));
+        assert_eq!(framebuffer.pixels().len(), 6);
+        assert!(framebuffer.depth().iter().all(|d| d.is_infinite()));
+        assert!(framebuffer.samples().iter().all(|&s| s == 0));
+
+        framebuffer.set(2, 1, Vec3(1.0, 2.0, 3.0));
+        framebuffer.set(0, 1, Vec3(4.0, 5.0, 6.0));
+        // the last pixel is the bottom right one
+        assert_eq!(rgb(framebuffer.pixels()[5]), (1.0, 2.0, 3.0));
+        assert_eq!(rgb(framebuffer.get(2, 1)), (1.0, 2.0, 3.0));
+
+        // rows run from the top
+        let rows: Vec<&[Color]> = framebuffer.rows().collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.len() == 3));
+        assert_eq!(rgb(rows[1][0]), (4.0, 5.0, 6.0));
+        assert_eq!(rgb(rows[1][2]), (1.0, 2.0, 3.0));
+        assert_eq!(rgb(rows[0][2]), (0.0, 0.0, 0.0));
+
+        framebuffer.map_pixels(|c| c * 2.0);
+        assert_eq!(rgb(framebuffer.get(0, 1)), (8.0, 10.0, 12.0));
+        assert_eq!(Framebuffer::new(0, 4).rows().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside the 3x2 framebuffer")]
+    fn test_outside() {
+        Framebuffer::new(3, 2).get(3, 0);
+    }
+
+    #[test]
+    fn test_sample_heatmap() {
+        let mut framebuffer = Framebuffer::new(4, 1);
+        framebuffer.samples_mut().copy_from_slice(&[0, 5, 10, 10]);
+        let heatmap = framebuffer.sample_heatmap();
+        let colors: Vec<_> = heatmap.pixels().iter().map(|&c| rgb(c)).collect();
+        // black, red turning yellow halfway, and white at the most samples
+        assert_eq!(
+            colors,
+            vec![
+                (0.0, 0.0, 0.0),
+                (1.0, 0.25, 0.0),
+                (1.0, 1.0, 1.0),
+                (1.0, 1.0, 1.0)
+            ]
+        );
+
+        // equal counts are all the most, and no samples at all is black
+        framebuffer.samples_mut().copy_from_slice(&[7, 7, 7, 7]);
+        let heatmap = framebuffer.sample_heatmap();
+        assert!(heatmap.pixels().iter().all(|&c| rgb(c) == (1.0, 1.0, 1.0)));
+        framebuffer.samples_mut().copy_from_slice(&[0, 0, 0, 0]);
+        let heatmap = framebuffer.sample_heatmap();
+        assert!(heatmap.pixels().iter().all(|&c| rgb(c) == (0.0, 0.0, 0.0)));
+    }
+}
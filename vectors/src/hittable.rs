@@ -1,23 +1,46 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::cuboid::*;
 use crate::interval::*;
 use crate::material::*;
+use crate::mesh::*;
+use crate::planar::*;
+use crate::quadric::*;
 use crate::ray::*;
+use crate::torus::*;
+use crate::triangle::*;
 use crate::vec3::*;
 
-pub struct HitRecord {
+// The material is borrowed from the object that was hit.
+pub struct HitRecord<'a> {
     pub point: Point3,
     pub normal: Vec3,
-    pub material: Material,
+    pub material: &'a Material,
     pub t: f64,
+    // surface coordinates of the hit point
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
 }
 
-impl HitRecord {
+// what a HitRecord points at before anything is hit
+static NO_MATERIAL: Material = Material::None;
+
+impl Default for HitRecord<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HitRecord<'_> {
     pub fn new() -> Self {
         Self {
             point: Point3::new(0.0, 0.0, 0.0),
             normal: Vec3::new(0.0, 0.0, 0.0),
-            material: Material::Lambertian(Color::new(0.0, 0.0, 0.0)),
+            material: &NO_MATERIAL,
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
         }
     }
@@ -35,17 +58,61 @@ impl HitRecord {
 pub enum HittableEnum {
     Sphere(Sphere),
     HalfSphere(HalfSphere),
+    BvhNode(BvhNode),
+    Triangle(Triangle),
+    Mesh(Mesh),
+    MeshTriangle(MeshTriangle),
+    Quad(Quad),
+    Plane(Plane),
+    Disk(Disk),
+    Cuboid(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Capsule(Capsule),
+    Torus(Torus),
 }
 
-pub trait Hittable {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool;
+pub trait Hittable: Send + Sync {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool;
+    fn bounding_box(&self) -> Aabb;
 }
 
 impl Hittable for HittableEnum {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
         match self {
             HittableEnum::Sphere(sphere) => sphere.hit(ray, ray_t, rec),
             HittableEnum::HalfSphere(sphere) => sphere.hit(ray, ray_t, rec),
+            HittableEnum::BvhNode(node) => node.hit(ray, ray_t, rec),
+            HittableEnum::Triangle(triangle) => triangle.hit(ray, ray_t, rec),
+            HittableEnum::Mesh(mesh) => mesh.hit(ray, ray_t, rec),
+            HittableEnum::MeshTriangle(triangle) => triangle.hit(ray, ray_t, rec),
+            HittableEnum::Quad(quad) => quad.hit(ray, ray_t, rec),
+            HittableEnum::Plane(plane) => plane.hit(ray, ray_t, rec),
+            HittableEnum::Disk(disk) => disk.hit(ray, ray_t, rec),
+            HittableEnum::Cuboid(cuboid) => cuboid.hit(ray, ray_t, rec),
+            HittableEnum::Cylinder(cylinder) => cylinder.hit(ray, ray_t, rec),
+            HittableEnum::Cone(cone) => cone.hit(ray, ray_t, rec),
+            HittableEnum::Capsule(capsule) => capsule.hit(ray, ray_t, rec),
+            HittableEnum::Torus(torus) => torus.hit(ray, ray_t, rec),
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            HittableEnum::Sphere(sphere) => sphere.bounding_box(),
+            HittableEnum::HalfSphere(sphere) => sphere.bounding_box(),
+            HittableEnum::BvhNode(node) => node.bounding_box(),
+            HittableEnum::Triangle(triangle) => triangle.bounding_box(),
+            HittableEnum::Mesh(mesh) => mesh.bounding_box(),
+            HittableEnum::MeshTriangle(triangle) => triangle.bounding_box(),
+            HittableEnum::Quad(quad) => quad.bounding_box(),
+            HittableEnum::Plane(plane) => plane.bounding_box(),
+            HittableEnum::Disk(disk) => disk.bounding_box(),
+            HittableEnum::Cuboid(cuboid) => cuboid.bounding_box(),
+            HittableEnum::Cylinder(cylinder) => cylinder.bounding_box(),
+            HittableEnum::Cone(cone) => cone.bounding_box(),
+            HittableEnum::Capsule(capsule) => capsule.bounding_box(),
+            HittableEnum::Torus(torus) => torus.bounding_box(),
         }
     }
 }
@@ -54,14 +121,28 @@ pub struct HittableList {
     pub objects: Vec<HittableEnum>,
 }
 
+impl HittableList {
+    // Replaces the flat object list with a single bounding volume hierarchy over all objects.
+    pub fn into_bvh(self) -> HittableList {
+        if self.objects.len() <= 1 {
+            return self;
+        }
+        HittableList {
+            objects: vec![BvhNode::build(self.objects)],
+        }
+    }
+}
+
 impl Hittable for HittableList {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
         let mut temp_rec = HitRecord {
             point: Point3::new(0.0, 0.0, 0.0),
             normal: Vec3::new(0.0, 0.0, 0.0),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
             front_face: false,
-            material: Material::None,
+            material: &NO_MATERIAL,
         };
 
         let mut hit_anything = false;
@@ -76,11 +157,30 @@ impl Hittable for HittableList {
                 rec.normal = temp_rec.normal;
                 rec.material = temp_rec.material;
                 rec.t = temp_rec.t;
+                rec.u = temp_rec.u;
+                rec.v = temp_rec.v;
                 rec.front_face = temp_rec.front_face;
             }
         }
         hit_anything
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects.iter().fold(Aabb::EMPTY, |acc, object| {
+            Aabb::enclosing(&acc, &object.bounding_box())
+        })
+    }
+}
+
+// Texture coordinates of a point on the unit sphere: u is the angle around the y axis
+// starting from -x, v the angle up from -y, both scaled to 0..1.
+pub fn sphere_uv(p: Point3) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z()).atan2(p.x()) + std::f64::consts::PI;
+    (
+        phi / (2.0 * std::f64::consts::PI),
+        theta / std::f64::consts::PI,
+    )
 }
 
 pub struct Sphere {
@@ -100,7 +200,7 @@ impl Sphere {
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
         let center = self.center;
         let radius = self.radius;
         let oc = center - ray.origin;
@@ -128,58 +228,254 @@ impl Hittable for Sphere {
         rec.point = ray.at(rec.t);
         let outward_normal = (rec.point - center) / radius;
         rec.set_face_normal(ray, outward_normal);
-        rec.material = self.material;
+        (rec.u, rec.v) = sphere_uv(outward_normal);
+        rec.material = &self.material;
         true
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let rvec = Vec3(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - rvec, self.center + rvec)
+    }
 }
 
+// The half of a sphere on the side of the plane through center that cut points to,
+// e.g. a dome for an upward cut. Without a cap the shape is an open shell; with one, a
+// flat disk closes it so it can be used as a solid (e.g. a glass hemisphere).
 pub struct HalfSphere {
     center: Point3,
     radius: f64,
     material: Material,
+    // unit normal of the cutting plane, pointing into the kept half
     cut: Vec3,
+    cap: bool,
 }
 
 impl HalfSphere {
+    // Panics if cut is zero, since it then doesn't say which half to keep.
     pub fn new(center: Point3, radius: f64, material: Material, cut: Vec3) -> Self {
+        assert!(
+            cut.length_squared() > 0.0,
+            "half sphere cut must not be zero"
+        );
         Self {
             center,
             radius,
             material,
-            cut,
+            cut: cut.unit_vector(),
+            cap: false,
         }
     }
-}
 
-impl Hittable for HalfSphere {
-    fn hit(&self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord) -> bool {
-        let center = self.center;
-        let radius = self.radius;
-        let oc = center - ray.origin;
+    pub fn with_cap(mut self, cap: bool) -> Self {
+        self.cap = cap;
+        self
+    }
+
+    // The nearest root in ray_t that lies on the kept half of the sphere.
+    fn hit_shell(&self, ray: &Ray, ray_t: Interval) -> Option<f64> {
+        let oc = self.center - ray.origin;
         let a = ray.dir.length_squared();
         let h = ray.dir.dot(oc);
-        let c = oc.length_squared() - radius * radius;
+        let c = oc.length_squared() - self.radius * self.radius;
         let discriminant = h * h - a * c;
 
         if discriminant < 0.0 {
-            return false;
+            return None;
         }
 
         let sqrtd = discriminant.sqrt();
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (h - sqrtd) / a;
-        if !ray_t.surrounds(root) {
-            root = (h + sqrtd) / a;
-            if !ray_t.surrounds(root) {
-                return false;
-            }
-        }
+        // Either root may be cut away, so check both in order.
+        [(h - sqrtd) / a, (h + sqrtd) / a]
+            .iter()
+            .copied()
+            .find(|&root| {
+                ray_t.surrounds(root) && (ray.at(root) - self.center).dot(self.cut) >= 0.0
+            })
+    }
 
-        rec.t = root;
-        rec.point = ray.at(rec.t);
-        let outward_normal = (rec.point - center) / radius;
-        rec.set_face_normal(ray, outward_normal);
-        rec.material = self.material;
+    // Where the ray crosses the cap disk, if it does so within ray_t.
+    fn hit_cap(&self, ray: &Ray, ray_t: Interval) -> Option<f64> {
+        hit_plane(ray, ray_t, self.center, self.cut)
+            .filter(|&t| (ray.at(t) - self.center).length_squared() <= self.radius * self.radius)
+    }
+
+    // Planar texture coordinates across the cap, 0..1 over its diameter.
+    fn cap_uv(&self, point: Point3) -> (f64, f64) {
+        let (tangent, bitangent) = plane_basis(-self.cut);
+        let d = point - self.center;
+        (
+            0.5 + d.dot(tangent) / (2.0 * self.radius),
+            0.5 + d.dot(bitangent) / (2.0 * self.radius),
+        )
+    }
+}
+
+impl Hittable for HalfSphere {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let shell = self.hit_shell(ray, ray_t);
+        let closest = shell.unwrap_or(ray_t.max);
+        let cap = if self.cap {
+            self.hit_cap(ray, Interval::new(ray_t.min, closest))
+        } else {
+            None
+        };
+
+        if let Some(t) = cap {
+            rec.t = t;
+            rec.point = ray.at(t);
+            // the cap faces away from the kept half
+            rec.set_face_normal(ray, -self.cut);
+            (rec.u, rec.v) = self.cap_uv(rec.point);
+        } else if let Some(t) = shell {
+            rec.t = t;
+            rec.point = ray.at(t);
+            let outward_normal = (rec.point - self.center) / self.radius;
+            rec.set_face_normal(ray, outward_normal);
+            (rec.u, rec.v) = sphere_uv(outward_normal);
+        } else {
+            return false;
+        }
+        rec.material = &self.material;
         true
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let rvec = Vec3(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - rvec, self.center + rvec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit dome around the origin, keeping y >= 0.
+    fn dome(cap: bool) -> HalfSphere {
+        HalfSphere::new(
+            Vec3(0.0, 0.0, 0.0),
+            1.0,
+            Material::None,
+            Vec3(0.0, 2.0, 0.0),
+        )
+        .with_cap(cap)
+    }
+
+    fn hit(sphere: &HalfSphere, origin: Point3, dir: Vec3) -> Option<HitRecord<'_>> {
+        let ray = Ray::new(origin, dir);
+        let mut rec = HitRecord::new();
+        if sphere.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec) {
+            Some(rec)
+        } else {
+            None
+        }
+    }
+
+    fn tuple(v: Vec3) -> (f64, f64, f64) {
+        (v.x(), v.y(), v.z())
+    }
+
+    #[test]
+    fn test_shell() {
+        let open = dome(false);
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        // from below, the first root is cut away and the ray hits the inside of the top
+        let up = Ray::new(Vec3(0.0, -3.0, 0.0), Vec3(0.0, 1.0, 0.0));
+        assert_eq!(open.hit_shell(&up, ray_t), Some(4.0));
+        let rec = hit(&open, up.origin, up.dir).unwrap();
+        assert_eq!(tuple(rec.point), (0.0, 1.0, 0.0));
+        assert!(!rec.front_face);
+        assert_eq!(tuple(rec.normal), (0.0, -1.0, 0.0));
+        // from above, the near root is kept
+        let down = Ray::new(Vec3(0.0, 3.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        assert_eq!(open.hit_shell(&down, ray_t), Some(2.0));
+        // both roots cut away
+        let low = Ray::new(Vec3(-3.0, -0.5, 0.0), Vec3(1.0, 0.0, 0.0));
+        assert_eq!(open.hit_shell(&low, ray_t), None);
+    }
+
+    #[test]
+    fn test_open_shell_from_inside() {
+        let (open, capped) = (dome(false), dome(true));
+        let rec = hit(&open, Vec3(0.0, 0.6, 0.0), Vec3(1.0, 0.0, 0.0)).unwrap();
+        assert!((rec.t - 0.8).abs() < 1e-12);
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3(-0.8, -0.6, 0.0)).length() < 1e-12);
+        // without a cap, nothing stops a ray leaving through the cut
+        assert!(hit(&open, Vec3(0.0, 0.5, 0.0), Vec3(0.0, -1.0, 0.0)).is_none());
+        // with one, it leaves through the back of the cap
+        let rec = hit(&capped, Vec3(0.0, 0.5, 0.0), Vec3(0.0, -1.0, 0.0)).unwrap();
+        assert_eq!(rec.t, 0.5);
+        assert!(!rec.front_face);
+        assert_eq!(tuple(rec.normal), (0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_cap() {
+        let capped = dome(true);
+        // from below, the cap is nearer than the inside of the shell
+        let rec = hit(&capped, Vec3(0.2, -3.0, 0.3), Vec3(0.0, 1.0, 0.0)).unwrap();
+        assert_eq!(rec.t, 3.0);
+        assert!(rec.front_face);
+        assert_eq!(tuple(rec.normal), (0.0, -1.0, 0.0));
+        // from above, the shell is nearer than the cap
+        let rec = hit(&capped, Vec3(0.0, 3.0, 0.0), Vec3(0.0, -1.0, 0.0)).unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(tuple(rec.normal), (0.0, 1.0, 0.0));
+        // outside the rim, the cap plane is missed
+        let ray = Ray::new(Vec3(1.2, -3.0, 0.0), Vec3(0.0, 1.0, 0.0));
+        let ray_t = Interval::new(0.001, f64::INFINITY);
+        assert_eq!(capped.hit_cap(&ray, ray_t), None);
+        assert!(hit(&capped, ray.origin, ray.dir).is_none());
+
+        // whichever way the cut points, the cap faces away from it
+        let cut = Vec3(1.0, -2.0, 0.5);
+        let sphere = HalfSphere::new(Vec3(1.0, 2.0, 3.0), 2.0, Material::None, cut).with_cap(true);
+        let rec = hit(&sphere, sphere.center - cut * 3.0, cut).unwrap();
+        assert!(rec.front_face);
+        assert!((rec.normal + cut.unit_vector()).length() < 1e-12);
+        assert!((rec.point - sphere.center).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_cap_uv() {
+        let sphere = dome(true);
+        assert_eq!(sphere.cap_uv(Vec3(0.0, 0.0, 0.0)), (0.5, 0.5));
+        let (tangent, bitangent) = plane_basis(-sphere.cut);
+        let uv = |p: Point3| {
+            let (u, v) = sphere.cap_uv(p);
+            ((u * 1e12).round() / 1e12, (v * 1e12).round() / 1e12)
+        };
+        assert_eq!(uv(tangent), (1.0, 0.5));
+        assert_eq!(uv(-tangent), (0.0, 0.5));
+        assert_eq!(uv(bitangent), (0.5, 1.0));
+        assert_eq!(uv(bitangent * -0.5), (0.5, 0.25));
+        // the hit record carries the same coordinates
+        let rec = hit(
+            &sphere,
+            tangent * 0.5 - Vec3(0.0, 1.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+        )
+        .unwrap();
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_grazing_rim() {
+        let (open, capped) = (dome(false), dome(true));
+        // tangent to the sphere exactly on the rim, where the cut keeps the boundary
+        for &sphere in [&open, &capped].iter() {
+            let rec = hit(sphere, Vec3(-3.0, 0.0, 1.0), Vec3(1.0, 0.0, 0.0)).unwrap();
+            assert_eq!(rec.t, 3.0);
+            assert_eq!(tuple(rec.point), (0.0, 0.0, 1.0));
+            // the ray is perpendicular to the normal, so either side counts as the front
+            assert_eq!(rec.normal.z().abs(), 1.0);
+        }
+        // across the cap plane through the rim, which is parallel to the cap
+        let rec = hit(&capped, Vec3(-3.0, 0.0, 0.0), Vec3(1.0, 0.0, 0.0)).unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert_eq!(tuple(rec.normal), (-1.0, 0.0, 0.0));
+        // just below the rim is cut away
+        assert!(hit(&open, Vec3(-3.0, -1e-9, 1e-3), Vec3(1.0, 0.0, 0.0)).is_none());
+    }
 }
@@ -0,0 +1,385 @@
+use crate::exr::*;
+use crate::framebuffer::*;
+use crate::png::*;
+use crate::vec3::*;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Encoders that turn a rendered framebuffer into image files.
+pub trait ImageWriter {
+    fn write(&self, out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()>;
+}
+
+// ASCII (P3) portable pixmap, the format the renderer has always produced.
+pub struct PpmWriter;
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()> {
+        write!(
+            out,
+            "P3\n{} {}\n255\n",
+            framebuffer.width(),
+            framebuffer.height()
+        )?;
+        for pixel in framebuffer.pixels() {
+            out.write_all(pixel.get_color().as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+// Binary (P6) portable pixmap, the same 8-bit data as P3 without the text overhead.
+pub struct BinaryPpmWriter;
+
+impl ImageWriter for BinaryPpmWriter {
+    fn write(&self, out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()> {
+        write!(
+            out,
+            "P6\n{} {}\n255\n",
+            framebuffer.width(),
+            framebuffer.height()
+        )?;
+        out.write_all(&framebuffer.to_rgb8())
+    }
+}
+
+// Portable float map: the linear radiance as 32-bit floats, before gamma and clamping.
+pub struct PfmWriter;
+
+impl ImageWriter for PfmWriter {
+    fn write(&self, out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()> {
+        // A negative scale marks the data as little-endian.
+        write!(
+            out,
+            "PF\n{} {}\n-1.0\n",
+            framebuffer.width(),
+            framebuffer.height()
+        )?;
+        let mut data = Vec::with_capacity(framebuffer.pixels().len() * 12);
+        // PFM stores rows from the bottom of the image up.
+        for row in framebuffer.rows().rev() {
+            for pixel in row {
+                for value in [pixel.x(), pixel.y(), pixel.z()] {
+                    data.extend_from_slice(&(value as f32).to_le_bytes());
+                }
+            }
+        }
+        out.write_all(&data)
+    }
+}
+
+pub struct PngWriter;
+
+impl ImageWriter for PngWriter {
+    fn write(&self, out: &mut dyn Write, framebuffer: &Framebuffer) -> io::Result<()> {
+        write_png(
+            out,
+            framebuffer.width(),
+            framebuffer.height(),
+            &framebuffer.to_rgb8(),
+        )
+    }
+}
+
+// Settings of the formats that have any, ignored by the others.
+#[derive(Debug, Clone, Copy)]
+pub struct WriterOptions {
+    pub exr_compression: ExrCompression,
+    // also write the depth and normal passes (exr only)
+    pub passes: bool,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            exr_compression: ExrCompression::Zip,
+            passes: false,
+        }
+    }
+}
+
+// Names accepted by writer_for_format.
+pub const FORMATS: [&str; 6] = ["ppm", "p3", "p6", "pfm", "png", "exr"];
+
+// Picks a writer by format name: "ppm" and "p3" are ASCII PPM, "p6" is binary PPM.
+pub fn writer_for_format(format: &str, options: &WriterOptions) -> Option<Box<dyn ImageWriter>> {
+    match format.to_ascii_lowercase().as_str() {
+        "ppm" | "p3" => Some(Box::new(PpmWriter)),
+        "p6" => Some(Box::new(BinaryPpmWriter)),
+        "pfm" => Some(Box::new(PfmWriter)),
+        "png" => Some(Box::new(PngWriter)),
+        "exr" => {
+            let mut writer = ExrWriter::new(options.exr_compression);
+            writer.passes = options.passes;
+            Some(Box::new(writer))
+        }
+        _ => None,
+    }
+}
+
+// Picks the writer matching the file extension of path, if it's a format we can encode.
+// .ppm files are written as ASCII P3, like they always were.
+pub fn writer_for_path(path: &Path, options: &WriterOptions) -> Option<Box<dyn ImageWriter>> {
+    writer_for_format(path.extension()?.to_str()?, options)
+}
+
+// An image read from a file: channel values scaled to 0..1 in the file's own encoding
+// (usually sRGB), row by row from the top. Gray images are expanded to RGB and alpha
+// is dropped.
+pub struct DecodedImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+// The largest images the decoders accept, so a corrupt header can't make them allocate
+// more memory than the machine has.
+pub const MAX_IMAGE_SIDE: usize = 1 << 16;
+pub const MAX_IMAGE_PIXELS: usize = 1 << 26;
+
+// The number of pixels of a width by height image, if it has any and isn't too large.
+pub(crate) fn pixel_count(width: usize, height: usize) -> Result<usize, String> {
+    if width == 0 || height == 0 {
+        return Err(String::from("image has no pixels"));
+    }
+    match width.checked_mul(height) {
+        Some(count)
+            if width <= MAX_IMAGE_SIDE && height <= MAX_IMAGE_SIDE && count <= MAX_IMAGE_PIXELS =>
+        {
+            Ok(count)
+        }
+        _ => Err(format!("{}x{} image is too large", width, height)),
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io { path: PathBuf, source: io::Error },
+    Decode { path: PathBuf, message: String },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ImageError::Decode { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io { source, .. } => Some(source),
+            ImageError::Decode { .. } => None,
+        }
+    }
+}
+
+// Reads a PNG or PPM file, telling them apart by their first bytes.
+pub fn load_image<P: AsRef<Path>>(path: P) -> Result<DecodedImage, ImageError> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|source| ImageError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    read_image(&data).map_err(|message| ImageError::Decode {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+pub fn read_image(data: &[u8]) -> Result<DecodedImage, String> {
+    if data.starts_with(b"\x89PNG") {
+        read_png(data)
+    } else if data.starts_with(b"P") {
+        read_ppm(data)
+    } else {
+        Err(String::from("unknown image format, expected PNG or PPM"))
+    }
+}
+
+// Decodes a portable graymap or pixmap, in text (P2, P3) or binary (P5, P6) form.
+pub fn read_ppm(data: &[u8]) -> Result<DecodedImage, String> {
+    let magic = data.get(..2).unwrap_or(&[]);
+    let (channels, binary) = match magic {
+        b"P2" => (1, false),
+        b"P3" => (3, false),
+        b"P5" => (1, true),
+        b"P6" => (3, true),
+        _ => return Err(String::from("not a PGM or PPM file (P2, P3, P5 or P6)")),
+    };
+
+    // Reads the next whitespace separated number, skipping # comments.
+    let mut position = 2;
+    let number = |position: &mut usize| -> Result<usize, String> {
+        loop {
+            match data.get(*position) {
+                Some(b'#') => {
+                    while data.get(*position).is_some_and(|&byte| byte != b'\n') {
+                        *position += 1;
+                    }
+                }
+                Some(byte) if byte.is_ascii_whitespace() => *position += 1,
+                _ => break,
+            }
+        }
+        let start = *position;
+        while data
+            .get(*position)
+            .is_some_and(|byte| byte.is_ascii_digit())
+        {
+            *position += 1;
+        }
+        std::str::from_utf8(&data[start..*position])
+            .unwrap()
+            .parse()
+            .map_err(|_| String::from("expected a number"))
+    };
+
+    let width = number(&mut position)?;
+    let height = number(&mut position)?;
+    let max_value = number(&mut position)?;
+    let count = pixel_count(width, height)? * channels;
+    if max_value == 0 || max_value > 65535 {
+        return Err(String::from("maximum value must be between 1 and 65535"));
+    }
+
+    // every sample takes at least a byte, so a short file is caught before allocating
+    if count > data.len() {
+        return Err(String::from("not enough image data"));
+    }
+    let samples: Vec<usize> = if binary {
+        // a single whitespace byte separates the header from the data
+        let start = position + 1;
+        let bytes_per_sample = if max_value < 256 { 1 } else { 2 };
+        let bytes = data
+            .get(start..start + count * bytes_per_sample)
+            .ok_or_else(|| String::from("not enough image data"))?;
+        if bytes_per_sample == 1 {
+            bytes.iter().map(|&byte| byte as usize).collect()
+        } else {
+            bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as usize)
+                .collect()
+        }
+    } else {
+        (0..count)
+            .map(|_| number(&mut position))
+            .collect::<Result<_, _>>()
+            .map_err(|_| String::from("not enough image data"))?
+    };
+
+    let scale = 1.0 / max_value as f64;
+    let value = |sample: usize| sample.min(max_value) as f64 * scale;
+    let pixels = samples
+        .chunks_exact(channels)
+        .map(|pixel| match pixel {
+            [gray] => Vec3(value(*gray), value(*gray), value(*gray)),
+            _ => Vec3(value(pixel[0]), value(pixel[1]), value(pixel[2])),
+        })
+        .collect();
+    Ok(DecodedImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(writer: Box<dyn ImageWriter>) -> Vec<u8> {
+        let mut framebuffer = Framebuffer::new(2, 1);
+        framebuffer.pixels_mut()[0] = Color::new(1.0, 0.0, 0.25);
+        let mut out = Vec::new();
+        writer.write(&mut out, &framebuffer).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_ppm_formats() {
+        // .ppm stays ASCII, binary output has to be asked for
+        let ascii =
+            encode(writer_for_path(Path::new("out.ppm"), &WriterOptions::default()).unwrap());
+        assert_eq!(
+            String::from_utf8(ascii).unwrap(),
+            "P3\n2 1\n255\n255 0 127\n0 0 0\n"
+        );
+        assert!(
+            encode(writer_for_format("P3", &WriterOptions::default()).unwrap())
+                .starts_with(b"P3\n")
+        );
+        let binary = encode(writer_for_format("p6", &WriterOptions::default()).unwrap());
+        assert_eq!(binary, b"P6\n2 1\n255\n\xff\x00\x7f\x00\x00\x00");
+        assert!(writer_for_path(Path::new("out.jpg"), &WriterOptions::default()).is_none());
+        for format in FORMATS.iter() {
+            assert!(writer_for_format(format, &WriterOptions::default()).is_some());
+        }
+    }
+
+    fn ppm_error(data: &[u8]) -> String {
+        match read_ppm(data) {
+            Err(message) => message,
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    fn colors(image: &DecodedImage) -> Vec<(f64, f64, f64)> {
+        image.pixels.iter().map(|c| (c.x(), c.y(), c.z())).collect()
+    }
+
+    #[test]
+    fn test_read_ppm() {
+        let ascii = read_ppm(b"P3\n# a comment\n2 1\n4\n4 0 2\n0 1 9\n").unwrap();
+        assert_eq!((ascii.width, ascii.height), (2, 1));
+        // values above the maximum are clamped
+        assert_eq!(colors(&ascii), vec![(1.0, 0.0, 0.5), (0.0, 0.25, 1.0)]);
+        let binary = read_ppm(b"P6 1 1 255\n\xff\x00\x33").unwrap();
+        assert_eq!(colors(&binary), vec![(1.0, 0.0, 0.2)]);
+        let gray = read_ppm(b"P2 2 1 10 5 10").unwrap();
+        assert_eq!(colors(&gray), vec![(0.5, 0.5, 0.5), (1.0, 1.0, 1.0)]);
+        let wide = read_ppm(b"P5 1 1 1000\n\x01\xf4").unwrap();
+        assert_eq!(colors(&wide), vec![(0.5, 0.5, 0.5)]);
+
+        // every encoded image reads back
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.pixels_mut()[4] = Color::new(1.0, 0.0, 1.0);
+        for format in ["p3", "p6"].iter() {
+            let writer = writer_for_format(format, &WriterOptions::default()).unwrap();
+            let mut out = Vec::new();
+            writer.write(&mut out, &framebuffer).unwrap();
+            let image = read_image(&out).unwrap();
+            assert_eq!((image.width, image.height), (3, 2));
+            assert_eq!(colors(&image)[4], (1.0, 0.0, 1.0));
+            assert_eq!(colors(&image)[3], (0.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn test_invalid_ppm() {
+        assert!(ppm_error(b"P7 1 1 255\n").contains("not a PGM or PPM"));
+        assert_eq!(ppm_error(b"P6 0 1 255\n"), "image has no pixels");
+        assert_eq!(
+            ppm_error(b"P6 1 1 0\n\x00\x00\x00"),
+            "maximum value must be between 1 and 65535"
+        );
+        assert_eq!(ppm_error(b"P6 x 1 255\n"), "expected a number");
+        // sizes that would overflow or exhaust memory are rejected before allocating
+        assert!(ppm_error(b"P6\n4294967296 4294967296\n255\n").contains("too large"));
+        assert!(ppm_error(b"P6\n18446744073709551615 2\n255\n").contains("too large"));
+        assert!(ppm_error(b"P3 100000 1 255\n0 0 0\n").contains("too large"));
+        assert_eq!(
+            ppm_error(b"P6 4000 4000 255\n\x00"),
+            "not enough image data"
+        );
+        assert_eq!(
+            ppm_error(b"P6 2 1 255\n\x00\x00\x00\x00"),
+            "not enough image data"
+        );
+        assert_eq!(ppm_error(b"P3 1 1 255\n0 0\n"), "not enough image data");
+    }
+}
@@ -0,0 +1,26 @@
+pub mod aabb;
+pub mod bvh;
+pub mod camera;
+pub mod cuboid;
+pub mod deflate;
+pub mod exr;
+pub mod framebuffer;
+pub mod hittable;
+pub mod image;
+pub mod interval;
+pub mod material;
+pub mod math;
+pub mod mesh;
+pub mod obj;
+pub mod perlin;
+pub mod planar;
+pub mod png;
+pub mod quadric;
+pub mod ray;
+pub mod sampler;
+pub mod scene;
+pub mod texture;
+pub mod torus;
+pub mod triangle;
+pub mod utils;
+pub mod vec3;
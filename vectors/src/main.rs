@@ -1,103 +1,324 @@
-mod camera;
-mod hittable;
-mod interval;
-mod material;
-mod ray;
-mod utils;
-mod vec3;
-use camera::*;
-use hittable::*;
-use ray::*;
-use std::io::Write;
-use vec3::*;
+use clap::Parser;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use vectors::camera::*;
+use vectors::exr::*;
+use vectors::framebuffer::*;
+use vectors::image::*;
+use vectors::sampler::*;
+use vectors::scene::*;
 
-fn main() {
-    //image
-    const ASPECT_RATIO: f64 = 16.0 / 9.0;
-    const IMAGE_WIDTH: i32 = 400;
-    const PIXEL_SAMPLE_SCALE: i32 = 100;
-    const MAX_DEPTH: i32 = 80;
-    const VFOV: f64 = 3.5;
-    const LOOK_FROM: Vec3 = Vec3(-40.0, 30.0, 30.0);
-    const LOOK_AT: Vec3 = Vec3(0.25, 0.5, -1.0);
-    const VUP: Vec3 = Vec3(0.0, 1.0, 0.0);
-
-    let material_ground = material::Material::new_metal(Vec3(0.8, 0.8, 0.0), 0.0);
-    let material_center = material::Material::Lambertian(Vec3(0.8, 0.6, 0.6));
-    // let material_surrounding = material::Material::new_metal(Vec3(1.0, 1.0, 1.0), 0.0);
-    let material_left = material::Material::Dielectric(1.5);
-    let material_bubble = material::Material::Dielectric(1.0 / 1.614);
-    let material_inside_bubble1 = material::Material::Lambertian(Vec3(0.2, 0.8, 0.6));
-    let material_inside_bubble2 = material::Material::Lambertian(Vec3(0.8, 1.0, 0.2));
-    let material_inside_bubble3 = material::Material::new_metal(Vec3(0.8, 1.0, 0.8), 0.0);
-    let material_right = material::Material::new_metal(Vec3(0.8, 0.8, 0.8), 0.4);
-    let mirror = material::Material::new_metal(Vec3(0.8, 0.8, 0.8), 0.0);
-    let mirror_red = material::Material::new_metal(Vec3(0.8, 0.2, 0.2), 0.0);
-
-    let world: HittableList = HittableList {
-        objects: vec![
-            HittableEnum::HalfSphere(HalfSphere::new(
-                Vec3(0.0, -100.5, -1.0),
-                100.0,
-                material_ground,
-                Vec3(0.0, 1.0, 0.0),
-            )),
-            HittableEnum::Sphere(Sphere::new(Vec3(0.0, 0.0, -1.2), 0.5, material_center)),
-            HittableEnum::Sphere(Sphere::new(Vec3(-1.0, 0.0, -1.0), 0.5, material_left)),
-            HittableEnum::Sphere(Sphere::new(Vec3(-1.0, 0.0, -1.0), 0.48, material_bubble)),
-            HittableEnum::Sphere(Sphere::new(
-                Vec3(-0.9, -0.1, -0.9),
-                0.2,
-                material_inside_bubble1,
-            )),
-            HittableEnum::Sphere(Sphere::new(
-                Vec3(-1.1, 0.1, -1.1),
-                0.2,
-                material_inside_bubble2,
-            )),
-            HittableEnum::Sphere(Sphere::new(
-                Vec3(-0.8, 0.2, -1.0),
-                0.2,
-                material_inside_bubble3,
-            )),
-            HittableEnum::Sphere(Sphere::new(Vec3(1.0, 0.0, -1.0), 0.5, material_right)),
-            HittableEnum::Sphere(Sphere::new(Vec3(1.0, -5.0, -16.0), 15.0, mirror)),
-            HittableEnum::Sphere(Sphere::new(Vec3(10.0, 30.0, 5.0), 20.0, mirror_red)),
-        ],
-    };
+/// Renders a scene description file to an image.
+///
+/// Options given on the command line take precedence over the values in the scene file.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Scene description file (TOML)
+    #[arg(default_value = "scenes/playaround.toml")]
+    scene: PathBuf,
+
+    /// Where to write the rendered image; the extension picks the format (ppm, pfm, png, exr)
+    #[arg(short, long, default_value = "images/playaround.ppm")]
+    output: PathBuf,
+
+    /// Image format, overriding the output extension (ppm and p3 are ASCII PPM, p6 is binary)
+    #[arg(short, long, value_parser = FORMATS)]
+    format: Option<String>,
+
+    /// Also write depth and normal passes as layers next to the beauty pass (exr only)
+    #[arg(long)]
+    passes: bool,
+
+    /// Compression used for exr output
+    #[arg(long, default_value = "zip", value_parser = ExrCompression::NAMES)]
+    compression: String,
+
+    /// Image width in pixels
+    #[arg(short, long, value_parser = clap::value_parser!(i32).range(1..))]
+    width: Option<i32>,
+
+    /// Width over height, either as a number (1.5) or a ratio (16:9)
+    #[arg(short, long, value_parser = parse_aspect_ratio)]
+    aspect_ratio: Option<f64>,
+
+    /// Number of random samples for each pixel (the maximum with --noise-threshold)
+    #[arg(short, long, value_parser = clap::value_parser!(i32).range(1..))]
+    samples_per_pixel: Option<i32>,
+
+    /// Fewest samples for each pixel with adaptive sampling
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    min_samples_per_pixel: Option<i32>,
+
+    /// Stop sampling a pixel once its estimated noise drops below this (e.g. 0.004), 0 disables it
+    #[arg(long, value_parser = parse_noise_threshold)]
+    noise_threshold: Option<f64>,
+
+    /// Also write an image of the number of samples taken for each pixel
+    #[arg(long)]
+    heatmap: Option<PathBuf>,
+
+    /// Maximum number of ray bounces
+    #[arg(short = 'd', long)]
+    max_depth: Option<i32>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    vfov: Option<f64>,
+
+    /// Seed for the random samples; equal seeds give identical images
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// How pixel and bounce samples are placed
+    #[arg(long, value_parser = SamplerKind::NAMES)]
+    sampler: Option<String>,
+
+    /// Number of render threads [default: available cores]
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u16).range(1..))]
+    threads: Option<u16>,
+}
+
+impl Args {
+    // Replaces the scene's camera settings with those given on the command line.
+    fn apply_overrides(&self, camera: &mut Camera) {
+        if let Some(width) = self.width {
+            camera.image_width = width;
+        }
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            camera.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(min_samples_per_pixel) = self.min_samples_per_pixel {
+            camera.min_samples_per_pixel = min_samples_per_pixel;
+        }
+        if let Some(noise_threshold) = self.noise_threshold {
+            camera.noise_threshold = noise_threshold;
+        }
+        if let Some(max_depth) = self.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(seed) = self.seed {
+            camera.seed = seed;
+        }
+        if let Some(sampler) = &self.sampler {
+            camera.sampler = SamplerKind::from_name(sampler).unwrap();
+        }
+        if let Some(threads) = self.threads {
+            camera.threads = threads as usize;
+        }
+    }
+}
 
-    let output = {
-        let mut file = std::fs::File::create("./images/playaround.ppm").expect("create failed");
-        move |text: String| {
-            file.write_all(text.as_bytes()).expect("write failed");
+fn parse_aspect_ratio(value: &str) -> Result<f64, String> {
+    let ratio = match value.split_once(':') {
+        Some((width, height)) => {
+            let width: f64 = width.trim().parse().map_err(|_| "invalid width")?;
+            let height: f64 = height.trim().parse().map_err(|_| "invalid height")?;
+            width / height
         }
+        None => value
+            .parse()
+            .map_err(|_| "expected a number or width:height")?,
+    };
+    if ratio.is_finite() && ratio > 0.0 {
+        Ok(ratio)
+    } else {
+        Err(String::from("must be positive"))
+    }
+}
+
+fn parse_noise_threshold(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(threshold) if threshold >= 0.0 => Ok(threshold),
+        Ok(_) => Err(String::from("must not be negative")),
+        Err(_) => Err(String::from("expected a number")),
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let format = match &args.format {
+        Some(format) => format.clone(),
+        None => args
+            .output
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase(),
+    };
+    let options = WriterOptions {
+        exr_compression: ExrCompression::from_name(&args.compression).unwrap(),
+        passes: args.passes,
     };
+    let writer = writer_for_format(&format, &options).unwrap_or_else(|| {
+        eprintln!(
+            "error: {}: unsupported image format, expected .ppm, .pfm, .png or .exr (or --format)",
+            args.output.display()
+        );
+        process::exit(1);
+    });
+    if args.passes && format != "exr" {
+        eprintln!("error: --passes needs exr output");
+        process::exit(1);
+    }
+
+    let Scene { mut camera, world } = load_scene(&args.scene).unwrap_or_else(|err| {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    });
+
+    args.apply_overrides(&mut camera);
+    camera.passes = args.passes;
+
+    let mut file = create_output(&args.output);
+    let heatmap = args.heatmap.as_ref().map(|path| {
+        let options = WriterOptions {
+            passes: false,
+            ..options
+        };
+        let writer = writer_for_path(path, &options).unwrap_or_else(|| {
+            eprintln!(
+                "error: {}: unsupported image format, expected .ppm, .pfm, .png or .exr",
+                path.display()
+            );
+            process::exit(1);
+        });
+        (path, writer, create_output(path))
+    });
 
-    Camera::new(
-        ASPECT_RATIO,
-        VFOV,
-        IMAGE_WIDTH,
-        PIXEL_SAMPLE_SCALE,
-        MAX_DEPTH,
-        LOOK_FROM,
-        LOOK_AT,
-        VUP,
-    )
-    .render(output, world);
-
-    //Render
-    // print each element of upper_left_corner
-    // println!("upper left corner --- {:?}", upper_left_corner);
-    // println!("{:?}", pixel00_loc);
-    // println!("{:?}", pixel_delta_u);
-    // println!("{:?}", pixel_delta_v);
-    //// let vecneg = vec1 - vec2;
-    // let vecmul = vec1 * 2.0;
-    // let vecdiv = vec1 / 2.0;
-    // println!("{:?}", vecadd);
-    // println!("{:?}", vecneg);
-    // println!("{:?}", vecmul);
-    // println!("{:?}", vecdiv);
-    // println!("{:?}", Vec3(1.0, 0.0, 0.0).length());
-    // println!("{:?}", Vec3(1.0, 0.0, 0.0).unit_vector());
+    let framebuffer = camera.render(world);
+    write_output(&args.output, writer.as_ref(), &mut file, &framebuffer);
+    if let Some((path, writer, mut file)) = heatmap {
+        write_output(
+            path,
+            writer.as_ref(),
+            &mut file,
+            &framebuffer.sample_heatmap(),
+        );
+    }
+}
+
+// Opens the output file before rendering, so a bad path doesn't waste the render.
+fn create_output(path: &Path) -> BufWriter<File> {
+    File::create(path)
+        .map(BufWriter::new)
+        .unwrap_or_else(|err| {
+            eprintln!("error: {}: {}", path.display(), err);
+            process::exit(1);
+        })
+}
+
+fn write_output(
+    path: &Path,
+    writer: &dyn ImageWriter,
+    file: &mut BufWriter<File>,
+    framebuffer: &Framebuffer,
+) {
+    writer
+        .write(file, framebuffer)
+        .and_then(|_| file.flush())
+        .unwrap_or_else(|err| {
+            eprintln!("error: {}: {}", path.display(), err);
+            process::exit(1);
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+    use vectors::vec3::*;
+
+    fn camera() -> Camera {
+        Camera::new(
+            1.0,
+            90.0,
+            100,
+            10,
+            10,
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn test_args() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_overrides() {
+        let args = Args::try_parse_from([
+            "vectors",
+            "scene.toml",
+            "-o",
+            "out.png",
+            "-w",
+            "320",
+            "-a",
+            "16:9",
+            "-s",
+            "64",
+            "-d",
+            "8",
+            "--vfov",
+            "40",
+            "--seed",
+            "7",
+            "-j",
+            "3",
+        ])
+        .unwrap();
+        assert_eq!(args.scene, Path::new("scene.toml"));
+        assert_eq!(args.output, Path::new("out.png"));
+
+        let mut camera = camera();
+        args.apply_overrides(&mut camera);
+        assert_eq!(camera.image_width, 320);
+        assert_eq!(camera.aspect_ratio, 16.0 / 9.0);
+        assert_eq!(camera.samples_per_pixel, 64);
+        assert_eq!(camera.max_depth, 8);
+        assert_eq!(camera.vfov, 40.0);
+        assert_eq!(camera.seed, 7);
+        assert_eq!(camera.threads, 3);
+    }
+
+    #[test]
+    fn test_scene_values_kept() {
+        // options that aren't given leave the scene's values alone
+        let args = Args::try_parse_from(["vectors", "--seed", "11"]).unwrap();
+        let mut camera = camera();
+        camera.seed = 3;
+        camera.threads = 5;
+        args.apply_overrides(&mut camera);
+        assert_eq!(camera.seed, 11);
+        assert_eq!(camera.threads, 5);
+        assert_eq!(camera.image_width, 100);
+        assert_eq!(camera.samples_per_pixel, 10);
+    }
+
+    #[test]
+    fn test_invalid_args() {
+        for args in [
+            &["vectors", "--seed", "-1"][..],
+            &["vectors", "--seed", "x"],
+            &["vectors", "-w", "0"],
+            &["vectors", "-a", "0:1"],
+            &["vectors", "-j", "0"],
+        ]
+        .iter()
+        {
+            assert!(Args::try_parse_from(args.iter()).is_err(), "{:?}", args);
+        }
+    }
 }
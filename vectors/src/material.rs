@@ -1,11 +1,15 @@
-use crate::utils::*;
+use crate::hittable::*;
+use crate::sampler::*;
+use crate::texture::*;
 use crate::{ray::Ray, vec3::*};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Material {
-    Lambertian(Color),
-    Metal(Vec3, f64),
+    Lambertian(Texture),
+    Metal(Texture, f64),
     Dielectric(f64),
+    // emits light of the given color and doesn't scatter
+    DiffuseLight(Color),
     None,
 }
 
@@ -16,7 +20,7 @@ impl Material {
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
 
-    pub fn new_metal(albedo: Vec3, fuzz: f64) -> Material {
+    pub fn new_metal<T: Into<Texture>>(albedo: T, fuzz: f64) -> Material {
         let f = {
             if fuzz < 1.0 {
                 fuzz
@@ -24,23 +28,32 @@ impl Material {
                 1.0
             }
         };
-        Material::Metal(albedo, f)
+        Material::Metal(albedo.into(), f)
+    }
+
+    // Light given off by the surface itself, independent of incoming rays.
+    pub fn emitted(&self) -> Color {
+        match self {
+            Material::DiffuseLight(emit) => *emit,
+            _ => Vec3(0.0, 0.0, 0.0),
+        }
     }
 
     pub fn scatter(
         &self,
         r_in: &Ray,
-        point: Point3,
-        normal: Vec3,
-        front_face: bool,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
     ) -> (bool, Ray, Color) {
+        let (point, normal) = (rec.point, rec.normal);
         match self {
             // albedo is the whiteness of the material
             Material::Lambertian(albedo) => {
                 let scatter_direction = {
-                    match near_zero(normal + random_unit_vector()) {
+                    let direction = normal + sampler.unit_vector();
+                    match near_zero(direction) {
                         true => normal,
-                        false => normal + random_unit_vector(),
+                        false => direction,
                     }
                 };
                 (
@@ -49,7 +62,7 @@ impl Material {
                         origin: point,
                         dir: scatter_direction,
                     },
-                    *albedo,
+                    albedo.value(rec.u, rec.v, point),
                 )
             }
 
@@ -59,9 +72,9 @@ impl Material {
                     true,
                     Ray {
                         origin: point,
-                        dir: reflected + random_unit_vector() * *fuzz,
+                        dir: reflected + sampler.unit_vector() * *fuzz,
                     },
-                    *albedo,
+                    albedo.value(rec.u, rec.v, point),
                 )
                 // scattered.dir.dot(normal) > 0.0
             }
@@ -69,7 +82,7 @@ impl Material {
             Material::Dielectric(refraction_index) => {
                 let attenuation = Vec3(1.0, 1.0, 1.0);
 
-                let ri = if front_face {
+                let ri = if rec.front_face {
                     1.0 / *refraction_index
                 } else {
                     *refraction_index
@@ -80,7 +93,7 @@ impl Material {
                 let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
                 let cannot_refract = ri * sin_theta > 1.0;
 
-                if cannot_refract || Self::reflectance(cos_theta, ri) > random_double() {
+                if cannot_refract || Self::reflectance(cos_theta, ri) > sampler.next_1d() {
                     (
                         true,
                         Ray {
@@ -109,3 +122,32 @@ impl Material {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diffuse_light() {
+        let light = Material::DiffuseLight(Vec3(4.0, 2.0, 1.0));
+        let emitted = light.emitted();
+        assert_eq!((emitted.x(), emitted.y(), emitted.z()), (4.0, 2.0, 1.0));
+
+        let mut rec = HitRecord::new();
+        rec.point = Vec3(0.0, 0.0, -1.0);
+        rec.normal = Vec3(0.0, 0.0, 1.0);
+        rec.front_face = true;
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        let mut sampler = Sampler::new(SamplerKind::Random, 1, 0, 0, 0);
+        sampler.start_sample(0);
+        // lights absorb everything that reaches them
+        let (scattered, _, attenuation) = light.scatter(&ray, &rec, &mut sampler);
+        assert!(!scattered);
+        assert_eq!(attenuation.length_squared(), 0.0);
+
+        // and nothing else emits
+        let lambertian = Material::Lambertian(Texture::Solid(Vec3(0.5, 0.5, 0.5)));
+        assert_eq!(lambertian.emitted().length_squared(), 0.0);
+        assert!(lambertian.scatter(&ray, &rec, &mut sampler).0);
+    }
+}
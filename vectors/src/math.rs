@@ -0,0 +1,292 @@
+// Real roots of polynomials up to degree four, for intersecting rays with implicit
+// surfaces.
+
+// At most four real roots in ascending order.
+#[derive(Debug, Clone, Copy)]
+pub struct Roots {
+    values: [f64; 4],
+    count: usize,
+}
+
+impl Roots {
+    const NONE: Roots = Roots {
+        values: [0.0; 4],
+        count: 0,
+    };
+
+    fn push(&mut self, root: f64) {
+        self.values[self.count] = root;
+        self.count += 1;
+    }
+
+    pub fn as_slice(&self) -> &[f64] {
+        &self.values[..self.count]
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+// The value of the polynomial with the given coefficients, highest degree first.
+pub fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().fold(0.0, |acc, &c| acc * x + c)
+}
+
+// Real roots of a x^2 + b x + c. The roots are computed without subtracting nearly equal
+// values, which would lose most of the precision of the smaller one.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Roots {
+    let mut roots = Roots::NONE;
+    if a == 0.0 {
+        if b != 0.0 {
+            roots.push(-c / b);
+        }
+        return roots;
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return roots;
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (r1, r2) = if q == 0.0 {
+        // b and the discriminant are both zero
+        (0.0, 0.0)
+    } else {
+        (q / a, c / q)
+    };
+    roots.push(r1.min(r2));
+    roots.push(r1.max(r2));
+    roots
+}
+
+// Real roots of a x^3 + b x^2 + c x + d.
+pub fn solve_cubic(a: f64, b: f64, c: f64, d: f64) -> Roots {
+    solve(&[a, b, c, d])
+}
+
+// Real roots of a x^4 + b x^3 + c x^2 + d x + e.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64, e: f64) -> Roots {
+    solve(&[a, b, c, d, e])
+}
+
+// Rather than the closed-form solutions, which lose precision badly for some coefficients,
+// this splits the real line at the roots of the derivative. Between two of those the
+// polynomial is monotonic, so a sign change brackets exactly one root, which a safeguarded
+// Newton iteration then finds to full precision. Double roots where the polynomial only
+// touches zero are found when it does so at a root of the derivative.
+fn solve(coefficients: &[f64]) -> Roots {
+    // Leading zeros lower the degree.
+    let start = coefficients
+        .iter()
+        .position(|&c| c != 0.0)
+        .unwrap_or(coefficients.len());
+    let p = &coefficients[start..];
+    if p.len() <= 3 {
+        return match *p {
+            [a, b, c] => solve_quadratic(a, b, c),
+            [b, c] => solve_quadratic(0.0, b, c),
+            _ => Roots::NONE,
+        };
+    }
+
+    let degree = p.len() - 1;
+    let mut derivative = [0.0; 4];
+    for (i, &c) in p[..degree].iter().enumerate() {
+        derivative[i] = c * (degree - i) as f64;
+    }
+    let critical = solve(&derivative[..degree]);
+
+    // Every root lies within this bound (Cauchy's bound).
+    let bound = 1.0 + p[1..].iter().map(|c| (c / p[0]).abs()).fold(0.0, f64::max);
+    let mut points = [0.0; 5];
+    points[0] = -bound;
+    let mut count = 1;
+    for &x in critical.as_slice() {
+        points[count] = x.clamp(-bound, bound);
+        count += 1;
+    }
+    points[count] = bound;
+    count += 1;
+
+    let mut roots = Roots::NONE;
+    // whether p(x) is zero up to the rounding errors of evaluating it
+    let is_zero = |x: f64, f: f64| f.abs() <= rounding_error(p, x);
+    for pair in points[..count].windows(2) {
+        let (lo, hi) = (pair[0], pair[1]);
+        let (f_lo, f_hi) = (evaluate(p, lo), evaluate(p, hi));
+        if is_zero(lo, f_lo) && lo != -bound {
+            // the polynomial touches or crosses zero at a root of the derivative
+            if roots.is_empty() || roots.values[roots.count - 1] != lo {
+                roots.push(lo);
+            }
+        } else if (f_lo < 0.0) != (f_hi < 0.0) && !is_zero(hi, f_hi) {
+            roots.push(refine(p, lo, hi, f_lo));
+        } else if f_hi == 0.0 && hi == bound {
+            roots.push(hi);
+        }
+    }
+    roots
+}
+
+// The root of p in [lo, hi], where p is monotonic and changes sign. Newton steps that
+// leave the bracket are replaced by bisection.
+fn refine(p: &[f64], mut lo: f64, mut hi: f64, f_lo: f64) -> f64 {
+    let rising = f_lo < 0.0;
+    let mut x = 0.5 * (lo + hi);
+    for _ in 0..100 {
+        let (f, df) = evaluate_with_derivative(p, x);
+        if f == 0.0 {
+            return x;
+        }
+        if (f < 0.0) == rising {
+            lo = x;
+        } else {
+            hi = x;
+        }
+        let newton = x - f / df;
+        let next = if newton > lo && newton < hi {
+            newton
+        } else {
+            0.5 * (lo + hi)
+        };
+        if (next - x).abs() <= f64::EPSILON * x.abs().max(1.0) {
+            return next;
+        }
+        x = next;
+    }
+    x
+}
+
+// A bound on the rounding error of evaluating p at x with Horner's scheme.
+fn rounding_error(p: &[f64], x: f64) -> f64 {
+    let magnitude = p.iter().fold(0.0, |acc, &c| acc * x.abs() + c.abs());
+    2.0 * p.len() as f64 * f64::EPSILON * magnitude
+}
+
+// The value and slope of p at x, with Horner's scheme.
+fn evaluate_with_derivative(p: &[f64], x: f64) -> (f64, f64) {
+    p.iter()
+        .fold((0.0, 0.0), |(f, df), &c| (f * x + c, df * x + f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    // The coefficients of the monic polynomial with the given roots.
+    fn from_roots(roots: &[f64]) -> Vec<f64> {
+        roots.iter().fold(vec![1.0], |p, &r| {
+            let mut next = p.clone();
+            next.push(0.0);
+            for (i, c) in p.iter().enumerate() {
+                next[i + 1] -= r * c;
+            }
+            next
+        })
+    }
+
+    fn assert_roots(found: Roots, expected: &[f64], tolerance: f64) {
+        assert_eq!(
+            found.len(),
+            expected.len(),
+            "found {:?}, expected {:?}",
+            found.as_slice(),
+            expected
+        );
+        for (a, b) in found.as_slice().iter().zip(expected) {
+            assert!(
+                (a - b).abs() <= tolerance * b.abs().max(1.0),
+                "found {:?}, expected {:?}",
+                found.as_slice(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_quadratic() {
+        assert_roots(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0], 1e-15);
+        assert_roots(solve_quadratic(1.0, 0.0, 1.0), &[], 0.0);
+        assert_roots(solve_quadratic(0.0, 2.0, -1.0), &[0.5], 1e-15);
+        // the small root would lose all its digits with the textbook formula
+        assert_roots(solve_quadratic(1.0, -1e8, 1.0), &[1e-8, 1e8], 1e-15);
+    }
+
+    #[test]
+    fn test_cubic() {
+        let p = from_roots(&[-2.0, 0.5, 3.0]);
+        assert_roots(
+            solve_cubic(p[0], p[1], p[2], p[3]),
+            &[-2.0, 0.5, 3.0],
+            1e-12,
+        );
+        // x^3 + x has one real root
+        assert_roots(solve_cubic(1.0, 0.0, 1.0, 0.0), &[0.0], 1e-12);
+    }
+
+    #[test]
+    fn test_quartic() {
+        let cases: [&[f64]; 5] = [
+            &[-3.0, -1.0, 0.5, 2.0],
+            &[0.001, 0.002, 1000.0, 1000.001],
+            &[-1e3, 1e-3, 1.0, 7.0],
+            &[1.0, 1.0 + 1e-6, 5.0, 6.0],
+            &[-0.5, 0.25, 0.75, 100.0],
+        ];
+        for roots in cases.iter() {
+            let p = from_roots(roots);
+            assert_roots(solve_quartic(p[0], p[1], p[2], p[3], p[4]), roots, 1e-9);
+        }
+        // (x^2 + 1)(x^2 - 4) has two real roots
+        assert_roots(
+            solve_quartic(1.0, 0.0, -3.0, 0.0, -4.0),
+            &[-2.0, 2.0],
+            1e-12,
+        );
+        // (x^2 + 1)(x^2 + 2) has none
+        assert_roots(solve_quartic(1.0, 0.0, 3.0, 0.0, 2.0), &[], 0.0);
+        // a double root where the polynomial only touches zero
+        let p = from_roots(&[-1.0, 2.0, 2.0, 4.0]);
+        let found = solve_quartic(p[0], p[1], p[2], p[3], p[4]);
+        assert!(found.as_slice().iter().any(|x| (x - 2.0).abs() < 1e-6));
+    }
+
+    // Every sign change of the polynomial over a fine grid must be close to a root found,
+    // and every root found must be a zero of the polynomial.
+    #[test]
+    fn test_quartic_against_brute_force() {
+        let mut rng = seeded_rng(5);
+        let mut random = || random_double_with_range(&mut rng, -4.0, 4.0);
+        for _ in 0..200 {
+            let p = [1.0, random(), random(), random(), random()];
+            let roots = solve_quartic(p[0], p[1], p[2], p[3], p[4]);
+            for &x in roots.as_slice() {
+                let slope = evaluate_with_derivative(&p, x).1.abs().max(1.0);
+                assert!(evaluate(&p, x).abs() / slope < 1e-9, "{:?} at {}", p, x);
+            }
+            let step = 1e-3;
+            let mut x = -12.0;
+            while x < 12.0 {
+                if (evaluate(&p, x) < 0.0) != (evaluate(&p, x + step) < 0.0) {
+                    assert!(
+                        roots
+                            .as_slice()
+                            .iter()
+                            .any(|&r| r >= x - step && r <= x + 2.0 * step),
+                        "{:?}: missed the root between {} and {}, found {:?}",
+                        p,
+                        x,
+                        x + step,
+                        roots.as_slice()
+                    );
+                }
+                x += step;
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::aabb::*;
+use crate::bvh::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::triangle::*;
+use crate::vec3::*;
+use std::sync::Arc;
+
+// Vertex attributes and triangle indices shared by every face of a mesh.
+// Each face indexes into positions, and into normals/uvs when those are present.
+pub struct MeshBuffers {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<Uv>,
+    pub indices: Vec<MeshIndices>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeshIndices {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+}
+
+impl MeshIndices {
+    pub fn new(positions: [usize; 3]) -> Self {
+        Self {
+            positions,
+            normals: None,
+            uvs: None,
+        }
+    }
+}
+
+pub struct Mesh {
+    buffers: Arc<MeshBuffers>,
+    // hierarchy over the MeshTriangles of this mesh
+    bvh: Box<HittableEnum>,
+}
+
+impl Mesh {
+    // Panics if the mesh has no faces or a face indexes past the end of a buffer.
+    pub fn new(buffers: MeshBuffers, material: Material) -> Self {
+        for face in buffers.indices.iter() {
+            let in_bounds = |indices: Option<[usize; 3]>, len: usize| {
+                indices.is_none_or(|i| i.iter().all(|&i| i < len))
+            };
+            assert!(
+                in_bounds(Some(face.positions), buffers.positions.len())
+                    && in_bounds(face.normals, buffers.normals.len())
+                    && in_bounds(face.uvs, buffers.uvs.len()),
+                "mesh face {:?} is out of bounds",
+                face
+            );
+        }
+
+        let buffers = Arc::new(buffers);
+        let triangles = (0..buffers.indices.len())
+            .map(|face| {
+                HittableEnum::MeshTriangle(MeshTriangle {
+                    buffers: Arc::clone(&buffers),
+                    face,
+                    material: material.clone(),
+                })
+            })
+            .collect();
+        Self {
+            buffers,
+            bvh: Box::new(BvhNode::build(triangles)),
+        }
+    }
+
+    pub fn buffers(&self) -> &MeshBuffers {
+        &self.buffers
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.bvh.hit(ray, ray_t, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+// A single face of a Mesh, reading its vertices from the shared buffers.
+pub struct MeshTriangle {
+    buffers: Arc<MeshBuffers>,
+    face: usize,
+    material: Material,
+}
+
+impl MeshTriangle {
+    fn gather<T: Copy>(buffer: &[T], indices: [usize; 3]) -> [T; 3] {
+        [buffer[indices[0]], buffer[indices[1]], buffer[indices[2]]]
+    }
+
+    fn vertices(&self) -> [Point3; 3] {
+        let indices = self.buffers.indices[self.face];
+        Self::gather(&self.buffers.positions, indices.positions)
+    }
+}
+
+impl Hittable for MeshTriangle {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let vertices = self.vertices();
+        match intersect(ray, ray_t, &vertices) {
+            Some((t, b1, b2)) => {
+                let indices = self.buffers.indices[self.face];
+                let normals = indices
+                    .normals
+                    .map(|i| Self::gather(&self.buffers.normals, i));
+                let uvs = indices.uvs.map(|i| Self::gather(&self.buffers.uvs, i));
+                let surface = Surface {
+                    vertices: &vertices,
+                    normals: normals.as_ref(),
+                    uvs: uvs.as_ref(),
+                };
+                surface.fill_record(ray, t, b1, b2, &self.material, rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(&self.vertices())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A unit square at z = -1 split into two faces, sharing its vertices, normals and
+    // texture coordinates.
+    fn square() -> MeshBuffers {
+        MeshBuffers {
+            positions: vec![
+                Vec3(0.0, 0.0, -1.0),
+                Vec3(1.0, 0.0, -1.0),
+                Vec3(1.0, 1.0, -1.0),
+                Vec3(0.0, 1.0, -1.0),
+            ],
+            normals: vec![Vec3(0.0, 0.0, 1.0)],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            indices: vec![
+                MeshIndices {
+                    positions: [0, 1, 2],
+                    normals: Some([0, 0, 0]),
+                    uvs: Some([0, 1, 2]),
+                },
+                MeshIndices {
+                    positions: [0, 2, 3],
+                    normals: None,
+                    uvs: Some([0, 2, 3]),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_hit() {
+        let mesh = Mesh::new(square(), Material::None);
+        for &(x, y) in [(0.7, 0.2), (0.2, 0.7)].iter() {
+            let ray = Ray::new(Vec3(x, y, 0.0), Vec3(0.0, 0.0, -1.0));
+            let mut rec = HitRecord::new();
+            assert!(mesh.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+            assert_eq!(rec.t, 1.0);
+            assert!(rec.front_face);
+            assert_eq!(
+                (rec.normal.x(), rec.normal.y(), rec.normal.z()),
+                (0.0, 0.0, 1.0)
+            );
+            // the shared texture coordinates span the square
+            assert!((rec.u - x).abs() < 1e-12 && (rec.v - y).abs() < 1e-12);
+        }
+        let ray = Ray::new(Vec3(1.2, 0.5, 0.0), Vec3(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(!mesh.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+
+        let bbox = mesh.bounding_box();
+        // padded, since the square is flat
+        assert!(bbox.x.contains(0.0) && bbox.x.contains(1.0) && bbox.x.size() < 1.001);
+        assert!(bbox.z.contains(-1.0) && bbox.z.size() < 0.001);
+        assert_eq!(mesh.buffers().indices.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn test_face_out_of_bounds() {
+        let mut buffers = square();
+        buffers.indices[1].uvs = Some([0, 2, 4]);
+        Mesh::new(buffers, Material::None);
+    }
+
+    #[test]
+    #[should_panic(expected = "without objects")]
+    fn test_no_faces() {
+        let mut buffers = square();
+        buffers.indices.clear();
+        Mesh::new(buffers, Material::None);
+    }
+}
@@ -0,0 +1,766 @@
+use crate::hittable::*;
+use crate::image::*;
+use crate::material::*;
+use crate::mesh::*;
+use crate::texture::*;
+use crate::triangle::Uv;
+use crate::vec3::*;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Loader for Wavefront .obj files, with materials read from the referenced .mtl libraries.
+// Faces are fan-triangulated and collected into one Mesh per group and material.
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+    // a texture map referenced by a material couldn't be loaded
+    Image(ImageError),
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Image(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+            ObjError::Image(err) => Some(err),
+        }
+    }
+}
+
+impl From<ImageError> for ObjError {
+    fn from(err: ImageError) -> Self {
+        ObjError::Image(err)
+    }
+}
+
+pub struct ObjGroup {
+    pub name: String,
+    // name given to usemtl, if any
+    pub material_name: Option<String>,
+    pub mesh: Mesh,
+}
+
+// Loads every group of the file. Faces without a usemtl, or whose material is not found
+// in any library, use default_material.
+pub fn load_obj<P: AsRef<Path>>(
+    path: P,
+    default_material: Material,
+) -> Result<Vec<ObjGroup>, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+    let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_obj(&source, path, base_dir, default_material)
+}
+
+// Loads the file and returns its meshes as hittables, ready to be added to a HittableList.
+pub fn load_obj_hittables<P: AsRef<Path>>(
+    path: P,
+    default_material: Material,
+) -> Result<Vec<HittableEnum>, ObjError> {
+    Ok(load_obj(path, default_material)?
+        .into_iter()
+        .map(|group| HittableEnum::Mesh(group.mesh))
+        .collect())
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    std::fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+// Reports parse errors against one file, so every message carries its path and line number.
+struct LineContext<'a> {
+    path: &'a Path,
+    line: usize,
+}
+
+impl LineContext<'_> {
+    fn error<S: Into<String>>(&self, message: S) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.line,
+            message: message.into(),
+        }
+    }
+
+    fn floats<'s, I: Iterator<Item = &'s str>>(
+        &self,
+        keyword: &str,
+        args: I,
+        min: usize,
+        max: usize,
+    ) -> Result<Vec<f64>, ObjError> {
+        let values = args
+            .map(|arg| {
+                arg.parse::<f64>()
+                    .map_err(|_| self.error(format!("invalid number '{}' in '{}'", arg, keyword)))
+            })
+            .collect::<Result<Vec<f64>, ObjError>>()?;
+        if values.len() < min || values.len() > max {
+            return Err(self.error(if min == max {
+                format!(
+                    "'{}' expects {} numbers, found {}",
+                    keyword,
+                    min,
+                    values.len()
+                )
+            } else {
+                format!(
+                    "'{}' expects {} to {} numbers, found {}",
+                    keyword,
+                    min,
+                    max,
+                    values.len()
+                )
+            }));
+        }
+        Ok(values)
+    }
+
+    fn vec3<'s, I: Iterator<Item = &'s str>>(
+        &self,
+        keyword: &str,
+        args: I,
+    ) -> Result<Vec3, ObjError> {
+        let v = self.floats(keyword, args, 3, 3)?;
+        Ok(Vec3(v[0], v[1], v[2]))
+    }
+}
+
+// One corner of a face, as zero-based indices into the file-wide attribute lists.
+#[derive(Clone, Copy)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Collects the faces of one group/material pair, re-indexing the attributes it uses
+// so that each mesh only stores its own vertices.
+struct GroupBuilder {
+    name: String,
+    material_name: Option<String>,
+    buffers: MeshBuffers,
+    position_map: HashMap<usize, usize>,
+    normal_map: HashMap<usize, usize>,
+    uv_map: HashMap<usize, usize>,
+}
+
+impl GroupBuilder {
+    fn new(name: String, material_name: Option<String>) -> Self {
+        Self {
+            name,
+            material_name,
+            buffers: MeshBuffers {
+                positions: Vec::new(),
+                normals: Vec::new(),
+                uvs: Vec::new(),
+                indices: Vec::new(),
+            },
+            position_map: HashMap::new(),
+            normal_map: HashMap::new(),
+            uv_map: HashMap::new(),
+        }
+    }
+
+    fn remap<T: Copy>(
+        map: &mut HashMap<usize, usize>,
+        buffer: &mut Vec<T>,
+        source: &[T],
+        index: usize,
+    ) -> usize {
+        *map.entry(index).or_insert_with(|| {
+            buffer.push(source[index]);
+            buffer.len() - 1
+        })
+    }
+
+    fn add_triangle(&mut self, corners: [Corner; 3], attributes: &Attributes) {
+        let positions = corners.map(|c| {
+            Self::remap(
+                &mut self.position_map,
+                &mut self.buffers.positions,
+                &attributes.positions,
+                c.position,
+            )
+        });
+        // Normals and uvs are only used when every corner of the triangle has one.
+        let normals = if corners.iter().all(|c| c.normal.is_some()) {
+            Some(corners.map(|c| {
+                Self::remap(
+                    &mut self.normal_map,
+                    &mut self.buffers.normals,
+                    &attributes.normals,
+                    c.normal.unwrap(),
+                )
+            }))
+        } else {
+            None
+        };
+        let uvs = if corners.iter().all(|c| c.uv.is_some()) {
+            Some(corners.map(|c| {
+                Self::remap(
+                    &mut self.uv_map,
+                    &mut self.buffers.uvs,
+                    &attributes.uvs,
+                    c.uv.unwrap(),
+                )
+            }))
+        } else {
+            None
+        };
+        self.buffers.indices.push(MeshIndices {
+            positions,
+            normals,
+            uvs,
+        });
+    }
+}
+
+struct Attributes {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Uv>,
+}
+
+// Parses obj source text. path is only used for error messages,
+// and mtllib references are resolved relative to base_dir.
+pub fn parse_obj(
+    source: &str,
+    path: &Path,
+    base_dir: &Path,
+    default_material: Material,
+) -> Result<Vec<ObjGroup>, ObjError> {
+    let mut attributes = Attributes {
+        positions: Vec::new(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+    };
+    let mut materials: HashMap<String, Material> = HashMap::new();
+    let mut groups: Vec<GroupBuilder> = Vec::new();
+    let mut group_lookup: HashMap<(String, Option<String>), usize> = HashMap::new();
+    let mut group_name = String::from("default");
+    let mut material_name: Option<String> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let ctx = LineContext {
+            path,
+            line: number + 1,
+        };
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                // An optional fourth (w) coordinate is accepted and ignored.
+                let v = ctx.floats(keyword, tokens, 3, 4)?;
+                attributes.positions.push(Vec3(v[0], v[1], v[2]));
+            }
+            "vn" => attributes.normals.push(ctx.vec3(keyword, tokens)?),
+            "vt" => {
+                let v = ctx.floats(keyword, tokens, 1, 3)?;
+                attributes
+                    .uvs
+                    .push((v[0], v.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                let corners = tokens
+                    .map(|token| parse_corner(&ctx, token, &attributes))
+                    .collect::<Result<Vec<Corner>, ObjError>>()?;
+                if corners.len() < 3 {
+                    return Err(ctx.error(format!(
+                        "face needs at least 3 vertices, found {}",
+                        corners.len()
+                    )));
+                }
+
+                let key = (group_name.clone(), material_name.clone());
+                let index = *group_lookup.entry(key).or_insert_with(|| {
+                    groups.push(GroupBuilder::new(group_name.clone(), material_name.clone()));
+                    groups.len() - 1
+                });
+                // Fan triangulation around the first corner.
+                for i in 1..corners.len() - 1 {
+                    groups[index]
+                        .add_triangle([corners[0], corners[i], corners[i + 1]], &attributes);
+                }
+            }
+            "g" | "o" => {
+                let names: Vec<&str> = tokens.collect();
+                group_name = if names.is_empty() {
+                    String::from("default")
+                } else {
+                    names.join(" ")
+                };
+            }
+            "usemtl" => {
+                let name: Vec<&str> = tokens.collect();
+                if name.is_empty() {
+                    return Err(ctx.error("'usemtl' expects a material name"));
+                }
+                material_name = Some(name.join(" "));
+            }
+            "mtllib" => {
+                let libraries: Vec<&str> = tokens.collect();
+                if libraries.is_empty() {
+                    return Err(ctx.error("'mtllib' expects a file name"));
+                }
+                for library in libraries {
+                    let mtl_path = base_dir.join(library);
+                    let mtl_source = read_file(&mtl_path)?;
+                    materials.extend(parse_mtl(&mtl_source, &mtl_path)?);
+                }
+            }
+            // smoothing groups, lines, points and other statements don't affect rendering
+            _ => {}
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|group| {
+            let material = group
+                .material_name
+                .as_ref()
+                .and_then(|name| materials.get(name))
+                .cloned()
+                .unwrap_or_else(|| default_material.clone());
+            ObjGroup {
+                name: group.name,
+                material_name: group.material_name,
+                mesh: Mesh::new(group.buffers, material),
+            }
+        })
+        .collect())
+}
+
+// Parses a face corner of the form v, v/vt, v//vn or v/vt/vn.
+fn parse_corner(
+    ctx: &LineContext,
+    token: &str,
+    attributes: &Attributes,
+) -> Result<Corner, ObjError> {
+    let mut parts = token.split('/');
+    let position = parts.next().unwrap_or("");
+    let uv = parts.next().filter(|s| !s.is_empty());
+    let normal = parts.next().filter(|s| !s.is_empty());
+    if parts.next().is_some() {
+        return Err(ctx.error(format!("invalid face vertex '{}'", token)));
+    }
+
+    let resolve = |index: &str, count: usize, kind: &str| -> Result<usize, ObjError> {
+        let value: i64 = index
+            .parse()
+            .map_err(|_| ctx.error(format!("invalid {} index '{}' in '{}'", kind, index, token)))?;
+        // Indices are 1-based, negative values count back from the latest element.
+        let resolved = match value {
+            v if v > 0 => v - 1,
+            v if v < 0 => count as i64 + v,
+            _ => return Err(ctx.error(format!("{} index 0 is not allowed", kind))),
+        };
+        if resolved < 0 || resolved >= count as i64 {
+            return Err(ctx.error(format!(
+                "{} index {} is out of range ({} defined so far)",
+                kind, value, count
+            )));
+        }
+        Ok(resolved as usize)
+    };
+
+    Ok(Corner {
+        position: resolve(position, attributes.positions.len(), "vertex")?,
+        uv: uv
+            .map(|i| resolve(i, attributes.uvs.len(), "texture coordinate"))
+            .transpose()?,
+        normal: normal
+            .map(|i| resolve(i, attributes.normals.len(), "normal"))
+            .transpose()?,
+    })
+}
+
+// The subset of an .mtl material that maps onto our Material variants.
+struct MtlMaterial {
+    diffuse: Color,
+    // map_Kd, replacing the diffuse color
+    diffuse_map: Option<Texture>,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    refraction_index: f64,
+    dissolve: f64,
+    illum: i64,
+}
+
+impl MtlMaterial {
+    fn new() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            diffuse_map: None,
+            specular: Color::new(0.0, 0.0, 0.0),
+            emission: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            refraction_index: 1.0,
+            dissolve: 1.0,
+            illum: 2,
+        }
+    }
+
+    // Emissive materials become DiffuseLight.
+    // Transparent or refractive illumination models become Dielectric,
+    // reflective ones become Metal with a fuzz derived from the specular exponent,
+    // everything else is Lambertian.
+    fn to_material(&self) -> Material {
+        let transparent = self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9);
+        let reflective = matches!(self.illum, 3 | 5 | 8);
+        if self.emission.length_squared() > 0.0 {
+            Material::DiffuseLight(self.emission)
+        } else if transparent {
+            Material::Dielectric(if self.refraction_index > 1.0 {
+                self.refraction_index
+            } else {
+                1.5
+            })
+        } else if reflective {
+            let albedo = if self.specular.length_squared() > 0.0 {
+                Texture::Solid(self.specular)
+            } else {
+                self.diffuse_texture()
+            };
+            let fuzz = (1.0 - self.shininess / 1000.0).clamp(0.0, 1.0);
+            Material::new_metal(albedo, fuzz)
+        } else {
+            Material::Lambertian(self.diffuse_texture())
+        }
+    }
+
+    fn diffuse_texture(&self) -> Texture {
+        self.diffuse_map
+            .clone()
+            .unwrap_or(Texture::Solid(self.diffuse))
+    }
+}
+
+// The file name of a texture map statement, after options like -blendu on or -s 2 2 1.
+// The rest of the line is the name, which may contain spaces.
+fn map_file_name<'a>(tokens: impl Iterator<Item = &'a str>) -> Option<String> {
+    let mut tokens = tokens.peekable();
+    while let Some(option) = tokens.next_if(|token| token.starts_with('-')) {
+        // -o, -s and -t take one to three numbers, -mm two values and the rest one
+        let (min, max) = match option {
+            "-o" | "-s" | "-t" => (1, 3),
+            "-mm" => (2, 2),
+            _ => (1, 1),
+        };
+        for i in 0..max {
+            let number = tokens
+                .peek()
+                .is_some_and(|token| token.parse::<f64>().is_ok());
+            if i >= min && !number {
+                break;
+            }
+            tokens.next();
+        }
+    }
+    let name: Vec<&str> = tokens.collect();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.join(" "))
+    }
+}
+
+fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, Material>, ObjError> {
+    let mut parsed: Vec<(String, MtlMaterial)> = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let ctx = LineContext {
+            path,
+            line: number + 1,
+        };
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name: Vec<&str> = tokens.collect();
+            if name.is_empty() {
+                return Err(ctx.error("'newmtl' expects a material name"));
+            }
+            parsed.push((name.join(" "), MtlMaterial::new()));
+            continue;
+        }
+
+        // Other texture maps and unknown statements are skipped.
+        if !matches!(
+            keyword,
+            "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "illum" | "map_Kd"
+        ) {
+            continue;
+        }
+        let current = match parsed.last_mut() {
+            Some((_, material)) => material,
+            None => return Err(ctx.error(format!("'{}' before any 'newmtl'", keyword))),
+        };
+        match keyword {
+            "Kd" => current.diffuse = ctx.vec3(keyword, tokens)?,
+            "Ks" => current.specular = ctx.vec3(keyword, tokens)?,
+            "Ke" => current.emission = ctx.vec3(keyword, tokens)?,
+            "Ns" => current.shininess = ctx.floats(keyword, tokens, 1, 1)?[0],
+            "Ni" => current.refraction_index = ctx.floats(keyword, tokens, 1, 1)?[0],
+            "d" => current.dissolve = ctx.floats(keyword, tokens, 1, 1)?[0],
+            "Tr" => current.dissolve = 1.0 - ctx.floats(keyword, tokens, 1, 1)?[0],
+            "map_Kd" => {
+                // The file name is resolved relative to the .mtl file.
+                let file = map_file_name(tokens)
+                    .ok_or_else(|| ctx.error("'map_Kd' expects a file name"))?;
+                let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+                let image = ImageTexture::load(base_dir.join(file))?;
+                current.diffuse_map = Some(Texture::Image(Arc::new(image)));
+            }
+            _ => {
+                let value = tokens.next().unwrap_or("");
+                current.illum = value
+                    .parse()
+                    .map_err(|_| ctx.error(format!("invalid illum model '{}'", value)))?;
+            }
+        }
+    }
+
+    Ok(parsed
+        .into_iter()
+        .map(|(name, material)| (name, material.to_material()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::*;
+    use crate::ray::*;
+
+    fn parse(source: &str) -> Result<Vec<ObjGroup>, ObjError> {
+        parse_obj(
+            source,
+            Path::new("test.obj"),
+            Path::new("/nonexistent"),
+            Material::None,
+        )
+    }
+
+    // the line number of a parse error
+    fn error_line(result: Result<Vec<ObjGroup>, ObjError>) -> usize {
+        match result {
+            Err(ObjError::Parse { line, .. }) => line,
+            Err(err) => panic!("expected a parse error, got {}", err),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    fn xyz(v: Vec3) -> (f64, f64, f64) {
+        (v.x(), v.y(), v.z())
+    }
+
+    const SQUARE: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n";
+
+    #[test]
+    fn test_negative_indices() {
+        let absolute = parse(&format!("{}f 1 2 3\n", SQUARE)).unwrap();
+        // -1 is the latest vertex, so -4 -3 -2 are the same three as 1 2 3
+        let relative = parse(&format!("{}f -4 -3 -2\n", SQUARE)).unwrap();
+        let (a, b) = (absolute[0].mesh.buffers(), relative[0].mesh.buffers());
+        assert_eq!(
+            a.positions.iter().copied().map(xyz).collect::<Vec<_>>(),
+            b.positions.iter().copied().map(xyz).collect::<Vec<_>>()
+        );
+        assert_eq!(a.indices[0].positions, b.indices[0].positions);
+
+        // relative indices only count the vertices defined so far
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 5 5 5\nf -4 -3 -1\n";
+        let groups = parse(source).unwrap();
+        let buffers = groups[0].mesh.buffers();
+        assert_eq!(buffers.indices.len(), 2);
+        let face = buffers.indices[1].positions;
+        assert_eq!(xyz(buffers.positions[face[2]]), (5.0, 5.0, 5.0));
+        assert_eq!(xyz(buffers.positions[face[0]]), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_corner_forms() {
+        let source = format!(
+            "{}vt 0 0\nvt 1 0\nvt 1 1\nvn 0 0 1\nf 1/1/1 2/2/1 3/3/1\nf 1//-1 3//1 4//1\nf 1/1 3/3 4/-3\n",
+            SQUARE
+        );
+        let groups = parse(&source).unwrap();
+        let buffers = groups[0].mesh.buffers();
+        assert_eq!(buffers.indices.len(), 3);
+        assert!(buffers.indices[0].uvs.is_some() && buffers.indices[0].normals.is_some());
+        assert!(buffers.indices[1].uvs.is_none() && buffers.indices[1].normals.is_some());
+        assert!(buffers.indices[2].uvs.is_some() && buffers.indices[2].normals.is_none());
+    }
+
+    #[test]
+    fn test_out_of_range_indices() {
+        assert_eq!(error_line(parse(&format!("{}f 1 2 5\n", SQUARE))), 5);
+        assert_eq!(error_line(parse(&format!("{}f 1 2 -5\n", SQUARE))), 5);
+        assert_eq!(error_line(parse(&format!("{}f 0 1 2\n", SQUARE))), 5);
+        // a vertex defined after the face doesn't count
+        assert_eq!(error_line(parse("v 0 0 0\nv 1 0 0\nf 1 2 3\nv 0 1 0\n")), 3);
+        assert_eq!(
+            error_line(parse(&format!("# comment\n\n{}f 1/2 2/2 3/2\n", SQUARE))),
+            7
+        );
+        assert_eq!(
+            error_line(parse(&format!("{}vn 0 0 1\nf 1//1 2//2 3//1\n", SQUARE))),
+            6
+        );
+        assert_eq!(error_line(parse(&format!("{}f 1 2\n", SQUARE))), 5);
+        assert_eq!(error_line(parse(&format!("{}f 1 x 3\n", SQUARE))), 5);
+    }
+
+    #[test]
+    fn test_polygon_faces() {
+        // a quad and a pentagon are fanned into 2 and 3 triangles
+        let source = format!("{}v 0.5 1.5 0\nf 1 2 3 4\nf 1 2 3 5 4\n", SQUARE);
+        let groups = parse(&source).unwrap();
+        let buffers = groups[0].mesh.buffers();
+        assert_eq!(buffers.positions.len(), 5);
+        let faces: Vec<[usize; 3]> = buffers.indices.iter().map(|i| i.positions).collect();
+        assert_eq!(faces.len(), 5);
+        let (a, b, c, d, e) = (0, 1, 2, 3, 4);
+        assert_eq!(
+            faces,
+            vec![[a, b, c], [a, c, d], [a, b, c], [a, c, e], [a, e, d]]
+        );
+    }
+
+    #[test]
+    fn test_groups_and_materials() {
+        let source = format!(
+            "{}g first\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 3 4\ng second\nf 1 2 4\ng first\nusemtl red\nf 2 3 4\n",
+            SQUARE
+        );
+        let groups = parse(&source).unwrap();
+        let summary: Vec<(&str, Option<&str>, usize)> = groups
+            .iter()
+            .map(|g| {
+                (
+                    g.name.as_str(),
+                    g.material_name.as_deref(),
+                    g.mesh.buffers().indices.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("first", Some("red"), 2),
+                ("first", Some("blue"), 1),
+                ("second", Some("blue"), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_material() {
+        // without any library, the named material falls back to the default one
+        let source = format!("{}usemtl missing\nf 1 2 3 4\n", SQUARE);
+        let groups = parse_obj(
+            &source,
+            Path::new("test.obj"),
+            Path::new(""),
+            Material::Dielectric(1.5),
+        )
+        .unwrap();
+        assert_eq!(groups[0].material_name.as_deref(), Some("missing"));
+        let ray = Ray::new(Vec3(0.5, 0.5, 1.0), Vec3(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        assert!(groups[0]
+            .mesh
+            .hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!(matches!(rec.material, Material::Dielectric(n) if *n == 1.5));
+    }
+
+    #[test]
+    fn test_missing_mtl() {
+        let source = format!("mtllib missing.mtl\n{}f 1 2 3\n", SQUARE);
+        match parse(&source) {
+            Err(ObjError::Io { path, .. }) => {
+                assert_eq!(path, Path::new("/nonexistent/missing.mtl"))
+            }
+            Err(err) => panic!("expected an io error, got {}", err),
+            Ok(_) => panic!("expected an io error"),
+        }
+    }
+
+    #[test]
+    fn test_mtl() {
+        let source = "newmtl glass\nNi 1.4\nd 0.5\nnewmtl red\nKd 0.8 0.1 0.1\n";
+        let materials = parse_mtl(source, Path::new("test.mtl")).unwrap();
+        assert!(matches!(materials["glass"], Material::Dielectric(n) if n == 1.4));
+        assert!(matches!(materials["red"], Material::Lambertian(_)));
+        let line = match parse_mtl("Kd 1 1 1\n", Path::new("test.mtl")) {
+            Err(ObjError::Parse { line, .. }) => line,
+            _ => panic!("expected a parse error"),
+        };
+        assert_eq!(line, 1);
+    }
+
+    #[test]
+    fn test_map_file_name() {
+        let name = |line: &str| map_file_name(line.split_whitespace());
+        assert_eq!(name("wood.png").as_deref(), Some("wood.png"));
+        assert_eq!(name("my wood.png").as_deref(), Some("my wood.png"));
+        assert_eq!(
+            name("-blendu off -s 2 2 -mm 0 1 old oak.png").as_deref(),
+            Some("old oak.png")
+        );
+        assert_eq!(name("-o 0.5 -clamp on 1.png").as_deref(), Some("1.png"));
+        assert_eq!(name("-o 0.5 -0.5 1 1.png").as_deref(), Some("1.png"));
+        assert_eq!(name("-blendu on"), None);
+        assert_eq!(name(""), None);
+
+        // the whole name is looked up next to the .mtl file
+        let source = "newmtl wood\nmap_Kd -bm 1 dark wood.png\n";
+        match parse_mtl(source, Path::new("/nonexistent/test.mtl")) {
+            Err(ObjError::Image(ImageError::Io { path, .. })) => {
+                assert_eq!(path, Path::new("/nonexistent/dark wood.png"))
+            }
+            Err(err) => panic!("expected an image error, got {}", err),
+            Ok(_) => panic!("expected an image error"),
+        }
+    }
+}
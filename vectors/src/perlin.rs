@@ -0,0 +1,124 @@
+use crate::utils::*;
+use crate::vec3::*;
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise: random unit gradients on the integer lattice, hashed by three
+// permutation tables and blended with a smooth cubic. Equal seeds give equal noise.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = seeded_rng(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| random_unit_vector(&mut rng))
+            .collect();
+        let permutation = |rng: &mut RenderRng| {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            shuffle(rng, &mut p);
+            p
+        };
+        let perm_x = permutation(&mut rng);
+        let perm_y = permutation(&mut rng);
+        let perm_z = permutation(&mut rng);
+        Self {
+            gradients,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    // A smooth value in roughly -1..1 that varies over distances of about one unit.
+    pub fn noise(&self, p: Point3) -> f64 {
+        let (u, v, w) = (
+            p.x() - p.x().floor(),
+            p.y() - p.y().floor(),
+            p.z() - p.z().floor(),
+        );
+        let (i, j, k) = (
+            p.x().floor() as i64,
+            p.y().floor() as i64,
+            p.z().floor() as i64,
+        );
+
+        // Hermite smoothing hides the lattice, which would otherwise show as a grid.
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let index = |perm: &[usize], n: i64| perm[(n & (POINT_COUNT as i64 - 1)) as usize];
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradients[index(&self.perm_x, i + di)
+                        ^ index(&self.perm_y, j + dj)
+                        ^ index(&self.perm_z, k + dk)];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // Sum of depth octaves of noise, each at twice the frequency and half the weight of
+    // the one before.
+    pub fn turbulence(&self, p: Point3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut point = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(point);
+            weight *= 0.5;
+            point *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        let perlin = Perlin::new(7);
+        let mut rng = seeded_rng(8);
+        let (mut low, mut high) = (0.0f64, 0.0f64);
+        for _ in 0..20000 {
+            let p = random_with_range(&mut rng, -50.0, 50.0);
+            let noise = perlin.noise(p);
+            // with unit gradients the noise can't exceed sqrt(3) / 2
+            assert!(noise.abs() <= 0.75f64.sqrt(), "noise {} at {:?}", noise, p);
+            (low, high) = (low.min(noise), high.max(noise));
+            // the octaves' weights add up to less than 2
+            let turbulence = perlin.turbulence(p, 7);
+            assert!((0.0..2.0 * 0.75f64.sqrt()).contains(&turbulence));
+        }
+        // and it uses most of that range
+        assert!(low < -0.5 && high > 0.5, "noise in {}..{}", low, high);
+        // noise is zero on the lattice
+        assert_eq!(perlin.noise(Vec3(3.0, -2.0, 5.0)), 0.0);
+    }
+
+    #[test]
+    fn test_seed() {
+        let p = Vec3(0.3, 1.7, -2.2);
+        assert_eq!(
+            Perlin::new(1).noise(p).to_bits(),
+            Perlin::new(1).noise(p).to_bits()
+        );
+        assert_ne!(Perlin::new(1).noise(p), Perlin::new(2).noise(p));
+    }
+}
@@ -0,0 +1,452 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+// Two unit vectors spanning the plane with the given unit normal, such that tangent,
+// bitangent and normal form a right-handed frame. Seen from the front, tangent points
+// right and bitangent up, e.g. +x and -z for a floor, +x and +y for a wall facing +z and
+// -x and +y for one facing -z.
+pub fn plane_basis(normal: Vec3) -> (Vec3, Vec3) {
+    // Right is up x normal, except on floors and ceilings, which have no up to go by.
+    let tangent = if normal.y().abs() < 0.9 {
+        Vec3(0.0, 1.0, 0.0).cross(normal).unit_vector()
+    } else {
+        (Vec3(1.0, 0.0, 0.0) - normal * normal.x()).unit_vector()
+    };
+    (tangent, normal.cross(tangent))
+}
+
+// Where a ray crosses the plane through point with the given normal, if it does so within
+// ray_t. Rays parallel to the plane miss it.
+pub(crate) fn hit_plane(ray: &Ray, ray_t: Interval, point: Point3, normal: Vec3) -> Option<f64> {
+    let denominator = ray.dir.dot(normal);
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    let t = (point - ray.origin).dot(normal) / denominator;
+    if ray_t.surrounds(t) {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+// A parallelogram with one corner at corner and the edges u and v leaving it. The front
+// face is the one u x v points out of. Texture coordinates run from 0 to 1 along u and v.
+pub struct Quad {
+    corner: Point3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    // u x v divided by its squared length, to find the hit's coordinates along u and v
+    w: Vec3,
+    material: Material,
+}
+
+impl Quad {
+    // Panics if u and v are zero or parallel, since the quad then has no area.
+    pub fn new(corner: Point3, u: Vec3, v: Vec3, material: Material) -> Self {
+        let n = u.cross(v);
+        assert!(
+            n.length_squared() > 0.0,
+            "quad edges must not be zero or parallel"
+        );
+        Self {
+            corner,
+            u,
+            v,
+            normal: n.unit_vector(),
+            w: n / n.length_squared(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let t = match hit_plane(ray, ray_t, self.corner, self.normal) {
+            Some(t) => t,
+            None => return false,
+        };
+        let point = ray.at(t);
+        let planar = point - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return false;
+        }
+
+        rec.t = t;
+        rec.point = point;
+        rec.set_face_normal(ray, self.normal);
+        (rec.u, rec.v) = (alpha, beta);
+        rec.material = &self.material;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::enclosing(
+            &Aabb::from_points(self.corner, self.corner + self.u + self.v),
+            &Aabb::from_points(self.corner + self.u, self.corner + self.v),
+        )
+    }
+}
+
+// An infinite plane through point, facing normal. The texture coordinates are distances
+// along the plane_basis vectors, so a texture that tiles 0..1 repeats every unit.
+pub struct Plane {
+    point: Point3,
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Material,
+}
+
+impl Plane {
+    // Panics if normal is zero, since it then doesn't say which way the plane faces.
+    pub fn new(point: Point3, normal: Vec3, material: Material) -> Self {
+        assert!(
+            normal.length_squared() > 0.0,
+            "plane normal must not be zero"
+        );
+        let normal = normal.unit_vector();
+        let (tangent, bitangent) = plane_basis(normal);
+        Self {
+            point,
+            normal,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let t = match hit_plane(ray, ray_t, self.point, self.normal) {
+            Some(t) => t,
+            None => return false,
+        };
+
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.set_face_normal(ray, self.normal);
+        let d = rec.point - self.point;
+        (rec.u, rec.v) = (d.dot(self.tangent), d.dot(self.bitangent));
+        rec.material = &self.material;
+        true
+    }
+
+    // Unbounded, except along an axis the plane is perpendicular to.
+    fn bounding_box(&self) -> Aabb {
+        let axis = |n: f64, p: f64| {
+            if n.abs() == 1.0 {
+                Interval::new(p, p)
+            } else {
+                Interval::UNIVERSE
+            }
+        };
+        Aabb::new(
+            axis(self.normal.x(), self.point.x()),
+            axis(self.normal.y(), self.point.y()),
+            axis(self.normal.z(), self.point.z()),
+        )
+    }
+}
+
+// A flat disk around center, facing normal. The texture coordinates run from 0 to 1
+// across its diameter, along the plane_basis vectors.
+pub struct Disk {
+    center: Point3,
+    normal: Vec3,
+    radius: f64,
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Material,
+}
+
+impl Disk {
+    // Panics if normal is zero, since it then doesn't say which way the disk faces, or if
+    // radius isn't positive (including NaN).
+    pub fn new(center: Point3, normal: Vec3, radius: f64, material: Material) -> Self {
+        assert!(
+            normal.length_squared() > 0.0,
+            "disk normal must not be zero"
+        );
+        assert!(radius > 0.0, "disk radius must be positive");
+        let normal = normal.unit_vector();
+        let (tangent, bitangent) = plane_basis(normal);
+        Self {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let t = match hit_plane(ray, ray_t, self.center, self.normal) {
+            Some(t) => t,
+            None => return false,
+        };
+        let point = ray.at(t);
+        let d = point - self.center;
+        if d.length_squared() > self.radius * self.radius {
+            return false;
+        }
+
+        rec.t = t;
+        rec.point = point;
+        rec.set_face_normal(ray, self.normal);
+        (rec.u, rec.v) = (
+            0.5 + d.dot(self.tangent) / (2.0 * self.radius),
+            0.5 + d.dot(self.bitangent) / (2.0 * self.radius),
+        );
+        rec.material = &self.material;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The disk reaches radius * sin(angle between normal and axis) along each axis.
+        let extent = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let e = Vec3(
+            extent(self.normal.x()),
+            extent(self.normal.y()),
+            extent(self.normal.z()),
+        );
+        Aabb::from_points(self.center - e, self.center + e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fires a ray at the object from origin along dir.
+    fn hit<'a>(object: &'a dyn Hittable, origin: Point3, dir: Vec3) -> Option<HitRecord<'a>> {
+        let ray = Ray::new(origin, dir);
+        let mut rec = HitRecord::new();
+        if object.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec) {
+            Some(rec)
+        } else {
+            None
+        }
+    }
+
+    // Fires a ray straight down -z at (x, y).
+    fn hit_at(object: &dyn Hittable, x: f64, y: f64) -> Option<(f64, f64)> {
+        hit(object, Vec3(x, y, 0.0), Vec3(0.0, 0.0, -1.0)).map(|rec| (rec.u, rec.v))
+    }
+
+    #[test]
+    fn test_quad() {
+        let quad = Quad::new(
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(2.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            Material::None,
+        );
+        // the corners are inside, with the corner coordinates
+        assert_eq!(hit_at(&quad, 0.0, 0.0), Some((0.0, 0.0)));
+        assert_eq!(hit_at(&quad, 2.0, 0.0), Some((1.0, 0.0)));
+        assert_eq!(hit_at(&quad, 0.0, 1.0), Some((0.0, 1.0)));
+        assert_eq!(hit_at(&quad, 2.0, 1.0), Some((1.0, 1.0)));
+        assert_eq!(hit_at(&quad, 1.0, 0.25), Some((0.5, 0.25)));
+        // just past each edge
+        assert_eq!(hit_at(&quad, -1e-9, 0.5), None);
+        assert_eq!(hit_at(&quad, 2.0 + 1e-9, 0.5), None);
+        assert_eq!(hit_at(&quad, 1.0, -1e-9), None);
+        assert_eq!(hit_at(&quad, 1.0, 1.0 + 1e-9), None);
+
+        let rec = hit(&quad, Vec3(1.0, 0.5, 0.0), Vec3(0.0, 0.0, -1.0)).unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert!(rec.front_face);
+        let rec = hit(&quad, Vec3(1.0, 0.5, -2.0), Vec3(0.0, 0.0, 1.0)).unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(
+            (rec.normal.x(), rec.normal.y(), rec.normal.z()),
+            (0.0, 0.0, -1.0)
+        );
+    }
+
+    #[test]
+    fn test_skewed_quad() {
+        // a parallelogram, whose sides aren't aligned with the axes
+        let quad = Quad::new(
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(1.0, 1.0, 0.0),
+            Material::None,
+        );
+        assert_eq!(hit_at(&quad, 1.5, 0.5), Some((1.0, 0.5)));
+        assert_eq!(hit_at(&quad, 1.0, 0.5), Some((0.5, 0.5)));
+        assert_eq!(hit_at(&quad, 0.4, 0.5), None);
+        assert_eq!(hit_at(&quad, 1.6, 0.5), None);
+        // inside the bounding box, but not the quad
+        assert_eq!(hit_at(&quad, 0.2, 0.9), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "parallel")]
+    fn test_degenerate_quad() {
+        let u = Vec3(1.0, 2.0, 3.0);
+        Quad::new(Vec3(0.0, 0.0, 0.0), u, u * -2.0, Material::None);
+    }
+
+    #[test]
+    fn test_parallel_rays() {
+        let objects: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Quad::new(
+                Vec3(-1.0, -1.0, -1.0),
+                Vec3(2.0, 0.0, 0.0),
+                Vec3(0.0, 2.0, 0.0),
+                Material::None,
+            )),
+            Box::new(Plane::new(
+                Vec3(0.0, 0.0, -1.0),
+                Vec3(0.0, 0.0, 1.0),
+                Material::None,
+            )),
+            Box::new(Disk::new(
+                Vec3(0.0, 0.0, -1.0),
+                Vec3(0.0, 0.0, 1.0),
+                1.0,
+                Material::None,
+            )),
+        ];
+        for object in objects.iter() {
+            assert!(hit(object.as_ref(), Vec3(-5.0, 0.0, 0.0), Vec3(1.0, 0.2, 0.0)).is_none());
+            // even within the plane itself
+            assert!(hit(object.as_ref(), Vec3(-5.0, 0.0, -1.0), Vec3(1.0, 0.0, 0.0)).is_none());
+        }
+    }
+
+    #[test]
+    fn test_plane_uv() {
+        let plane = Plane::new(Vec3(1.0, 2.0, 3.0), Vec3(1.0, -2.0, 0.5), Material::None);
+        let point = plane.point + plane.tangent * 2.5 - plane.bitangent * 4.0;
+        let rec = hit(&plane, point + plane.normal * 3.0, -plane.normal).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-12);
+        assert!((rec.u - 2.5).abs() < 1e-12 && (rec.v + 4.0).abs() < 1e-12);
+        // distances, so a unit step along a basis vector moves a whole unit
+        let rec = hit(&plane, point + plane.tangent + plane.normal, -plane.normal).unwrap();
+        assert!((rec.u - 3.5).abs() < 1e-12 && (rec.v + 4.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_plane_bounding_box() {
+        let plane = Plane::new(Vec3(1.0, 2.0, 3.0), Vec3(0.0, -4.0, 0.0), Material::None);
+        let bbox = plane.bounding_box();
+        assert!(bbox.y.contains(2.0) && bbox.y.size() < 0.01);
+        assert_eq!(
+            (bbox.x.size(), bbox.z.size()),
+            (f64::INFINITY, f64::INFINITY)
+        );
+
+        let tilted = Plane::new(Vec3(1.0, 2.0, 3.0), Vec3(0.0, 1.0, 1.0), Material::None);
+        let bbox = tilted.bounding_box();
+        for axis in 0..3 {
+            assert_eq!(bbox.axis_interval(axis).size(), f64::INFINITY);
+        }
+    }
+
+    #[test]
+    fn test_disk() {
+        let disk = Disk::new(
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.0, 0.0, 1.0),
+            2.0,
+            Material::None,
+        );
+        assert_eq!(hit_at(&disk, 0.0, 0.0), Some((0.5, 0.5)));
+        // on the rim
+        assert_eq!(hit_at(&disk, 2.0, 0.0), Some((1.0, 0.5)));
+        assert_eq!(hit_at(&disk, 0.0, -2.0), Some((0.5, 0.0)));
+        // just outside it, including the corners of the disk's square
+        assert_eq!(hit_at(&disk, 2.0 + 1e-9, 0.0), None);
+        assert_eq!(hit_at(&disk, 0.0, -2.0 - 1e-9), None);
+        assert_eq!(hit_at(&disk, 1.5, 1.5), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "radius must be positive")]
+    fn test_zero_disk_radius() {
+        Disk::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, 1.0),
+            0.0,
+            Material::None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "radius must be positive")]
+    fn test_nan_disk_radius() {
+        Disk::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, 1.0),
+            f64::NAN,
+            Material::None,
+        );
+    }
+
+    #[test]
+    fn test_plane_basis() {
+        let tuple = |v: Vec3| (v.x(), v.y(), v.z());
+        let (tangent, bitangent) = plane_basis(Vec3(0.0, 1.0, 0.0));
+        assert_eq!(
+            (tuple(tangent), tuple(bitangent)),
+            ((1.0, 0.0, 0.0), (0.0, 0.0, -1.0))
+        );
+        let (tangent, bitangent) = plane_basis(Vec3(0.0, 0.0, 1.0));
+        assert_eq!(
+            (tuple(tangent), tuple(bitangent)),
+            ((1.0, 0.0, 0.0), (0.0, 1.0, 0.0))
+        );
+        // walls facing away from +z stay upright too
+        let (tangent, bitangent) = plane_basis(Vec3(0.0, 0.0, -1.0));
+        assert_eq!(
+            (tuple(tangent), tuple(bitangent)),
+            ((-1.0, 0.0, 0.0), (0.0, 1.0, 0.0))
+        );
+        let (tangent, bitangent) = plane_basis(Vec3(1.0, 0.0, 0.0));
+        assert_eq!(
+            (tuple(tangent), tuple(bitangent)),
+            ((0.0, 0.0, -1.0), (0.0, 1.0, 0.0))
+        );
+        let (tangent, _) = plane_basis(Vec3(-0.6, 0.0, -0.8));
+        assert!((tangent - Vec3(-0.8, 0.0, 0.6)).length() < 1e-12);
+
+        let normals = [
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(-1.0, 0.0, 0.0),
+            Vec3(0.95, 0.3, -0.1),
+            Vec3(-0.95, -0.1, 0.3),
+            Vec3(0.9, 0.4, 0.1),
+            Vec3(0.0, 1.0, 0.0),
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(0.3, -0.5, 0.8),
+        ];
+        for normal in normals.iter() {
+            let normal = normal.unit_vector();
+            let (tangent, bitangent) = plane_basis(normal);
+            assert!((tangent.length() - 1.0).abs() < 1e-12, "{:?}", normal);
+            assert!((bitangent.length() - 1.0).abs() < 1e-12, "{:?}", normal);
+            assert!(tangent.dot(normal).abs() < 1e-12, "{:?}", normal);
+            assert!(bitangent.dot(normal).abs() < 1e-12, "{:?}", normal);
+            // right-handed, with the normal as the third axis
+            assert!(
+                (tangent.cross(bitangent) - normal).length() < 1e-12,
+                "{:?}",
+                normal
+            );
+        }
+    }
+}
@@ -0,0 +1,583 @@
+use crate::deflate::*;
+use crate::image::*;
+use crate::vec3::*;
+use std::io::{self, Write};
+
+// PNG encoding of 8-bit RGB images, and decoding of every standard PNG layout.
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    for (n, entry) in table.iter_mut().enumerate() {
+        let mut c = n as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *entry = c;
+    }
+    table
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let table = crc32_table();
+    !data.iter().fold(!0u32, |crc, &byte| {
+        table[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+fn write_chunk(out: &mut dyn Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    let mut crc_data = Vec::with_capacity(kind.len() + data.len());
+    crc_data.extend_from_slice(kind);
+    crc_data.extend_from_slice(data);
+    out.write_all(&crc32(&crc_data).to_be_bytes())
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Applies filter type kind to one scanline. bpp is the number of bytes per pixel.
+fn filter_row(kind: u8, row: &[u8], prior: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    out.push(kind);
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prior[i];
+        let c = if i >= bpp { prior[i - bpp] } else { 0 };
+        out.push(match kind {
+            1 => row[i].wrapping_sub(a),
+            2 => row[i].wrapping_sub(b),
+            3 => row[i].wrapping_sub(((a as u16 + b as u16) / 2) as u8),
+            4 => row[i].wrapping_sub(paeth(a, b, c)),
+            _ => row[i],
+        });
+    }
+}
+
+// Encodes width x height RGB pixels (3 bytes each, rows top to bottom) as a PNG.
+pub fn write_png(out: &mut dyn Write, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    assert_eq!(
+        rgb.len(),
+        width * height * 3,
+        "pixel data doesn't match the image size"
+    );
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // bit depth 8, color type 2 (RGB), deflate compression, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    // Pick the filter with the smallest sum of absolute differences for each row,
+    // the heuristic recommended by the PNG specification.
+    let stride = width * 3;
+    let zero_row = vec![0u8; stride];
+    let mut filtered = Vec::with_capacity((stride + 1) * height);
+    let mut candidate = Vec::with_capacity(stride + 1);
+    for y in 0..height {
+        let row = &rgb[y * stride..(y + 1) * stride];
+        let prior = if y == 0 {
+            &zero_row[..]
+        } else {
+            &rgb[(y - 1) * stride..y * stride]
+        };
+        let mut best: Option<(u64, Vec<u8>)> = None;
+        for kind in 0..5 {
+            candidate.clear();
+            filter_row(kind, row, prior, 3, &mut candidate);
+            let score = candidate[1..]
+                .iter()
+                .map(|&v| (v as i8).unsigned_abs() as u64)
+                .sum();
+            if best
+                .as_ref()
+                .is_none_or(|(best_score, _)| score < *best_score)
+            {
+                best = Some((score, candidate.clone()));
+            }
+        }
+        filtered.extend(best.unwrap().1);
+    }
+
+    out.write_all(&SIGNATURE)?;
+    write_chunk(out, b"IHDR", &header)?;
+    write_chunk(out, b"IDAT", &zlib_compress(&filtered))?;
+    write_chunk(out, b"IEND", &[])
+}
+
+// Adam7 interlacing passes: first column and row, then the column and row steps.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+// Reverses filter type kind on one scanline in place.
+fn unfilter_row(kind: u8, row: &mut [u8], prior: &[u8], bpp: usize) -> Result<(), String> {
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let b = prior[i];
+        let c = if i >= bpp { prior[i - bpp] } else { 0 };
+        row[i] = row[i].wrapping_add(match kind {
+            0 => 0,
+            1 => a,
+            2 => b,
+            3 => ((a as u16 + b as u16) / 2) as u8,
+            4 => paeth(a, b, c),
+            _ => return Err(format!("unknown filter type {}", kind)),
+        });
+    }
+    Ok(())
+}
+
+// Sample index of a scanline, for bit depths 1, 2, 4, 8 and 16.
+fn sample(row: &[u8], index: usize, depth: u8) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            // Samples smaller than a byte are packed from the most significant bit.
+            let bit = index * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+        }
+    }
+}
+
+struct PngHeader {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl PngHeader {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+
+    // The color of pixel x of an unfiltered scanline. Alpha is ignored.
+    fn color(&self, row: &[u8], x: usize, palette: &[Color]) -> Result<Color, String> {
+        let channels = self.channels();
+        let scale = 1.0 / ((1u32 << self.depth) - 1) as f64;
+        let value = |channel: usize| sample(row, x * channels + channel, self.depth) as f64 * scale;
+        Ok(match self.color_type {
+            0 | 4 => Vec3(value(0), value(0), value(0)),
+            3 => {
+                let index = sample(row, x, self.depth) as usize;
+                *palette
+                    .get(index)
+                    .ok_or_else(|| format!("palette index {} out of range", index))?
+            }
+            _ => Vec3(value(0), value(1), value(2)),
+        })
+    }
+}
+
+fn read_header(data: &[u8]) -> Result<PngHeader, String> {
+    if data.len() != 13 {
+        return Err(String::from("invalid IHDR chunk"));
+    }
+    let header = PngHeader {
+        width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize,
+        height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize,
+        depth: data[8],
+        color_type: data[9],
+        interlaced: data[12] == 1,
+    };
+    let depth_allowed = match header.color_type {
+        0 => matches!(header.depth, 1 | 2 | 4 | 8 | 16),
+        3 => matches!(header.depth, 1 | 2 | 4 | 8),
+        2 | 4 | 6 => matches!(header.depth, 8 | 16),
+        _ => return Err(format!("unknown color type {}", header.color_type)),
+    };
+    if !depth_allowed {
+        return Err(format!(
+            "bit depth {} is not allowed for color type {}",
+            header.depth, header.color_type
+        ));
+    }
+    pixel_count(header.width, header.height)?;
+    if data[10] != 0 || data[11] != 0 || data[12] > 1 {
+        return Err(String::from(
+            "unknown compression, filter or interlace method",
+        ));
+    }
+    Ok(header)
+}
+
+// Decodes a PNG file into colors scaled to 0..1, still in the file's (usually sRGB) encoding.
+pub fn read_png(data: &[u8]) -> Result<DecodedImage, String> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(String::from("not a PNG file"));
+    }
+
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    let mut position = SIGNATURE.len();
+    loop {
+        let truncated = || String::from("truncated file");
+        let length_bytes = data.get(position..position + 4).ok_or_else(truncated)?;
+        let length = u32::from_be_bytes([
+            length_bytes[0],
+            length_bytes[1],
+            length_bytes[2],
+            length_bytes[3],
+        ]) as usize;
+        let chunk = data
+            .get(position + 4..position + 8 + length + 4)
+            .ok_or_else(truncated)?;
+        let (kind, rest) = chunk.split_at(4);
+        let (body, crc) = rest.split_at(length);
+        if crc32(&chunk[..4 + length]).to_be_bytes() != crc {
+            return Err(format!(
+                "bad checksum in {} chunk",
+                String::from_utf8_lossy(kind)
+            ));
+        }
+        position += 12 + length;
+
+        match kind {
+            b"IHDR" => header = Some(read_header(body)?),
+            b"PLTE" => {
+                palette = body
+                    .chunks_exact(3)
+                    .map(|rgb| {
+                        Vec3(
+                            rgb[0] as f64 / 255.0,
+                            rgb[1] as f64 / 255.0,
+                            rgb[2] as f64 / 255.0,
+                        )
+                    })
+                    .collect()
+            }
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            // Chunks with a lowercase first letter are optional, the rest are needed to decode.
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => {
+                return Err(format!(
+                    "unsupported {} chunk",
+                    String::from_utf8_lossy(kind)
+                ))
+            }
+        }
+    }
+
+    let header = header.ok_or_else(|| String::from("missing IHDR chunk"))?;
+    if header.color_type == 3 && palette.is_empty() {
+        return Err(String::from("missing PLTE chunk"));
+    }
+
+    let bits_per_pixel = header.channels() * header.depth as usize;
+    let bpp = bits_per_pixel.div_ceil(8);
+    let passes = if header.interlaced {
+        &ADAM7[..]
+    } else {
+        &[(0, 0, 1, 1)][..]
+    };
+    // Each pass is a smaller image of every dx-th column and dy-th row, as columns, rows
+    // and the bytes per scanline.
+    let pass_sizes: Vec<(usize, usize, usize)> = passes
+        .iter()
+        .map(|&(x0, y0, dx, dy)| {
+            let columns = (header.width + dx - 1 - x0) / dx;
+            let rows = (header.height + dy - 1 - y0) / dy;
+            (columns, rows, (columns * bits_per_pixel).div_ceil(8))
+        })
+        .collect();
+    let needed: usize = pass_sizes
+        .iter()
+        .filter(|&&(columns, _, _)| columns > 0)
+        .map(|&(_, rows, stride)| rows * (1 + stride))
+        .sum();
+    // The filtered data is exactly that long, which also caps the decompression.
+    let raw = zlib_decompress(&compressed, needed).map_err(|err| err.to_string())?;
+    if raw.len() < needed {
+        return Err(String::from("not enough image data"));
+    }
+
+    let mut pixels = vec![Vec3(0.0, 0.0, 0.0); header.width * header.height];
+    let mut position = 0;
+    for (&(x0, y0, dx, dy), &(columns, rows, stride)) in passes.iter().zip(pass_sizes.iter()) {
+        if columns == 0 || rows == 0 {
+            continue;
+        }
+        let mut prior = vec![0u8; stride];
+        for row_index in 0..rows {
+            let line = &raw[position..position + 1 + stride];
+            let mut row = line[1..].to_vec();
+            unfilter_row(line[0], &mut row, &prior, bpp)?;
+            position += 1 + stride;
+
+            let y = y0 + row_index * dy;
+            for column in 0..columns {
+                let x = x0 + column * dx;
+                pixels[y * header.width + x] = header.color(&row, column, &palette)?;
+            }
+            prior = row;
+        }
+    }
+
+    Ok(DecodedImage {
+        width: header.width,
+        height: header.height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"a"), 0xe8b7be43);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(
+            crc32(b"The quick brown fox jumps over the lazy dog"),
+            0x414fa339
+        );
+        // the checksum every PNG ends with
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+    }
+
+    fn encode(width: usize, height: usize, rgb: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_png(&mut out, width, height, rgb).unwrap();
+        out
+    }
+
+    // A PNG file made of the given chunks.
+    fn png(chunks: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut out = SIGNATURE.to_vec();
+        for (kind, data) in chunks.iter() {
+            write_chunk(&mut out, kind, data).unwrap();
+        }
+        out
+    }
+
+    fn ihdr(width: u32, height: u32) -> Vec<u8> {
+        ihdr_with(width, height, [8, 2, 0])
+    }
+
+    // An IHDR chunk with the given bit depth, color type and interlace method.
+    fn ihdr_with(width: u32, height: u32, [depth, color_type, interlace]: [u8; 3]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        header.extend([depth, color_type, 0, 0, interlace]);
+        header
+    }
+
+    // A PNG file of the given filtered scanlines, with a PLTE chunk unless palette is empty.
+    fn fixture(width: u32, height: u32, fields: [u8; 3], palette: &[u8], data: &[u8]) -> Vec<u8> {
+        let header = ihdr_with(width, height, fields);
+        let compressed = zlib_compress(data);
+        let mut chunks: Vec<(&[u8; 4], &[u8])> = vec![(b"IHDR", &header)];
+        if !palette.is_empty() {
+            chunks.push((b"PLTE", palette));
+        }
+        chunks.push((b"IDAT", &compressed));
+        chunks.push((b"IEND", &[]));
+        png(&chunks)
+    }
+
+    // The decoded pixels as 8-bit RGB.
+    fn rgb8(image: &DecodedImage) -> Vec<u8> {
+        image
+            .pixels
+            .iter()
+            .flat_map(|c| vec![c.x(), c.y(), c.z()])
+            .map(|value| (value * 255.0).round() as u8)
+            .collect()
+    }
+
+    // The decoded pixels of a grayscale image as 8-bit values.
+    fn gray8(image: &DecodedImage) -> Vec<u8> {
+        let rgb = rgb8(image);
+        for pixel in rgb.chunks(3) {
+            assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+        }
+        rgb.iter().step_by(3).copied().collect()
+    }
+
+    fn error(data: &[u8]) -> String {
+        match read_png(data) {
+            Err(message) => message,
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let (width, height) = (7, 5);
+        let rgb: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i * 37 % 256) as u8)
+            .collect();
+        let image = read_png(&encode(width, height, &rgb)).unwrap();
+        assert_eq!((image.width, image.height), (width, height));
+        assert_eq!(rgb8(&image), rgb);
+    }
+
+    #[test]
+    fn test_palette() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255];
+        // 2-bit indices 0, 1, 2 then 3, 3, 0, each row padded to a whole byte
+        let data = [0, 0b00_01_10_00, 0, 0b11_11_00_00];
+        let image = read_png(&fixture(3, 2, [2, 3, 0], &palette, &data)).unwrap();
+        assert_eq!(
+            rgb8(&image),
+            [255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255, 255, 255, 255, 255, 0, 0]
+        );
+        assert_eq!(
+            error(&fixture(3, 2, [2, 3, 0], &palette[..6], &data)),
+            "palette index 2 out of range"
+        );
+        assert_eq!(
+            error(&fixture(3, 2, [2, 3, 0], &[], &data)),
+            "missing PLTE chunk"
+        );
+    }
+
+    #[test]
+    fn test_grayscale() {
+        // a row with the sub filter, then one with the up filter
+        let data = [1, 10, 20, 2, 5, 5];
+        let image = read_png(&fixture(2, 2, [8, 0, 0], &[], &data)).unwrap();
+        assert_eq!(gray8(&image), [10, 30, 15, 35]);
+
+        // gray and alpha, of which the alpha is ignored
+        let data = [0, 100, 255, 200, 0];
+        let image = read_png(&fixture(2, 1, [8, 4, 0], &[], &data)).unwrap();
+        assert_eq!(gray8(&image), [100, 200]);
+    }
+
+    #[test]
+    fn test_16_bit() {
+        let data = [0, 0xff, 0xff, 0x80, 0x00, 0x01, 0x00];
+        let image = read_png(&fixture(1, 1, [16, 2, 0], &[], &data)).unwrap();
+        let color = image.pixels[0];
+        assert_eq!(
+            (color.x(), color.y(), color.z()),
+            (1.0, 32768.0 / 65535.0, 256.0 / 65535.0)
+        );
+
+        let data = [0, 0x12, 0x34, 0xff, 0xff];
+        let image = read_png(&fixture(1, 1, [16, 4, 0], &[], &data)).unwrap();
+        assert_eq!(image.pixels[0].x(), 0x1234 as f64 / 65535.0);
+    }
+
+    #[test]
+    fn test_sub_byte_depths() {
+        // ten 1-bit samples spill into a second byte
+        let data = [0, 0b1010_1100, 0b1100_0000];
+        let image = read_png(&fixture(10, 1, [1, 0, 0], &[], &data)).unwrap();
+        assert_eq!(gray8(&image), [255, 0, 255, 0, 255, 255, 0, 0, 255, 255]);
+
+        let data = [0, 0x0f, 0x50];
+        let image = read_png(&fixture(3, 1, [4, 0, 0], &[], &data)).unwrap();
+        assert_eq!(gray8(&image), [0, 255, 85]);
+
+        // the sub filter works on whole bytes, even when a pixel is smaller
+        let data = [1, 0b0101_0101, 0b0101_0101];
+        let image = read_png(&fixture(8, 1, [2, 0, 0], &[], &data)).unwrap();
+        assert_eq!(gray8(&image), [85, 85, 85, 85, 170, 170, 170, 170]);
+    }
+
+    #[test]
+    fn test_interlaced() {
+        // The Adam7 passes of a 3x3 image whose pixel (x, y) is 10 * (3y + x). Passes 2 and
+        // 3 are empty, passes 6 and 7 have two rows and one row.
+        let data = [
+            0, 0, // pass 1: (0, 0)
+            0, 20, // pass 4: (2, 0)
+            0, 60, 80, // pass 5: (0, 2) and (2, 2)
+            0, 10, 0, 70, // pass 6: (1, 0), then (1, 2)
+            0, 30, 40, 50, // pass 7: row 1
+        ];
+        let image = read_png(&fixture(3, 3, [8, 0, 1], &[], &data)).unwrap();
+        assert_eq!(gray8(&image), [0, 10, 20, 30, 40, 50, 60, 70, 80]);
+
+        assert_eq!(
+            error(&fixture(3, 3, [8, 0, 1], &[], &data[..data.len() - 1])),
+            "not enough image data"
+        );
+        let mut extra = data.to_vec();
+        extra.push(0);
+        assert_eq!(
+            error(&fixture(3, 3, [8, 0, 1], &[], &extra)),
+            "invalid compressed data: decompressed data is too large"
+        );
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let file = encode(3, 2, &[200; 18]);
+        for length in 0..file.len() {
+            assert!(read_png(&file[..length]).is_err(), "{} bytes", length);
+        }
+        let mut corrupt = file.clone();
+        // a byte of the IHDR chunk, which its checksum catches
+        corrupt[20] ^= 1;
+        assert!(read_png(&corrupt).is_err());
+
+        // too large, or with far less data than the header promises
+        let data = zlib_compress(&[0; 16]);
+        let huge = png(&[
+            (b"IHDR", &ihdr(u32::MAX, u32::MAX)),
+            (b"IDAT", &data),
+            (b"IEND", &[]),
+        ]);
+        assert!(error(&huge).contains("too large"));
+        let short = png(&[
+            (b"IHDR", &ihdr(4000, 4000)),
+            (b"IDAT", &data),
+            (b"IEND", &[]),
+        ]);
+        assert_eq!(error(&short), "not enough image data");
+        // a small stream that inflates to far more than the image needs
+        let bomb = png(&[
+            (b"IHDR", &ihdr(1, 1)),
+            (b"IDAT", &zlib_compress(&[0; 1 << 20])),
+            (b"IEND", &[]),
+        ]);
+        assert_eq!(
+            error(&bomb),
+            "invalid compressed data: decompressed data is too large"
+        );
+        let empty = png(&[(b"IHDR", &ihdr(0, 4)), (b"IEND", &[])]);
+        assert!(read_png(&empty).is_err());
+        let missing = png(&[(b"IDAT", &data), (b"IEND", &[])]);
+        assert!(read_png(&missing).is_err());
+    }
+}
@@ -0,0 +1,495 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::planar::*;
+use crate::ray::*;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+// The side of a cone frustum around the segment from base to top, with base_radius at
+// the base and top_radius at the top. Equal radii make it a cylinder.
+struct Tube {
+    base: Point3,
+    // unit vector from base to top
+    axis: Vec3,
+    height: f64,
+    base_radius: f64,
+    top_radius: f64,
+    // the frame texture coordinates measure angles in
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Tube {
+    // Panics if base and top are the same point, which leaves no axis to measure along.
+    fn new(base: Point3, top: Point3, base_radius: f64, top_radius: f64) -> Self {
+        let height = (top - base).length();
+        assert!(height > 0.0, "base and top must differ");
+        let axis = (top - base) / height;
+        let (tangent, bitangent) = plane_basis(axis);
+        Self {
+            base,
+            axis,
+            height,
+            base_radius,
+            top_radius,
+            tangent,
+            bitangent,
+        }
+    }
+
+    fn top(&self) -> Point3 {
+        self.base + self.axis * self.height
+    }
+
+    // how much the radius grows per unit along the axis
+    fn slope(&self) -> f64 {
+        (self.top_radius - self.base_radius) / self.height
+    }
+
+    // The part of v perpendicular to the axis.
+    fn radial(&self, v: Vec3) -> Vec3 {
+        v - self.axis * v.dot(self.axis)
+    }
+
+    // The nearest root in ray_t whose hit lies between the base and the top.
+    fn hit_side(&self, ray: &Ray, ray_t: Interval) -> Option<f64> {
+        // |radial(p - base)|^2 = (base_radius + slope * height along axis)^2, written as
+        // a t^2 - 2 h t + c = 0 like in Sphere::hit.
+        let k = self.slope();
+        let oc = ray.origin - self.base;
+        let (oc_radial, dir_radial) = (self.radial(oc), self.radial(ray.dir));
+        let (oc_axial, dir_axial) = (oc.dot(self.axis), ray.dir.dot(self.axis));
+        let radius_at_origin = self.base_radius + k * oc_axial;
+
+        let a = dir_radial.length_squared() - k * k * dir_axial * dir_axial;
+        let h = k * dir_axial * radius_at_origin - dir_radial.dot(oc_radial);
+        let c = oc_radial.length_squared() - radius_at_origin * radius_at_origin;
+
+        let roots = if a.abs() < 1e-12 {
+            // The ray runs parallel to the cone's slant, so it crosses it only once.
+            if h == 0.0 {
+                return None;
+            }
+            [c / (2.0 * h), f64::NAN]
+        } else {
+            let discriminant = h * h - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrtd = discriminant.sqrt();
+            let (r1, r2) = ((h - sqrtd) / a, (h + sqrtd) / a);
+            // a is negative when the ray is steeper than the slant
+            [r1.min(r2), r1.max(r2)]
+        };
+        roots.iter().copied().find(|&root| {
+            let along = oc_axial + root * dir_axial;
+            ray_t.surrounds(root) && (0.0..=self.height).contains(&along)
+        })
+    }
+
+    fn side_normal(&self, point: Point3) -> Vec3 {
+        // the gradient of the implicit surface
+        let d = point - self.base;
+        let radius = self.base_radius + self.slope() * d.dot(self.axis);
+        (self.radial(d) - self.axis * (radius * self.slope())).unit_vector()
+    }
+
+    // u is the angle around the axis and v the height along it, both 0..1.
+    fn side_uv(&self, point: Point3) -> (f64, f64) {
+        let d = point - self.base;
+        (
+            self.angle(d),
+            (d.dot(self.axis) / self.height).clamp(0.0, 1.0),
+        )
+    }
+
+    fn angle(&self, d: Vec3) -> f64 {
+        (d.dot(self.bitangent).atan2(d.dot(self.tangent)) + PI) / (2.0 * PI)
+    }
+
+    // The nearest of the base and top disks the ray crosses within ray_t, as the ray
+    // parameter and the disk's outward normal. A zero radius end has no disk.
+    fn hit_caps(&self, ray: &Ray, ray_t: Interval) -> Option<(f64, Vec3)> {
+        let disks = [
+            (self.base, -self.axis, self.base_radius),
+            (self.top(), self.axis, self.top_radius),
+        ];
+        let mut closest: Option<(f64, Vec3)> = None;
+        for &(center, normal, radius) in disks.iter() {
+            if radius <= 0.0 {
+                continue;
+            }
+            let max = closest.map_or(ray_t.max, |(t, _)| t);
+            if let Some(t) = hit_plane(ray, Interval::new(ray_t.min, max), center, normal) {
+                if (ray.at(t) - center).length_squared() <= radius * radius {
+                    closest = Some((t, normal));
+                }
+            }
+        }
+        closest
+    }
+
+    // Planar texture coordinates across a cap, 0..1 over the larger diameter.
+    fn cap_uv(&self, point: Point3, normal: Vec3) -> (f64, f64) {
+        let (tangent, bitangent) = plane_basis(normal);
+        let radius = self.base_radius.max(self.top_radius);
+        let d = self.radial(point - self.base);
+        (
+            0.5 + d.dot(tangent) / (2.0 * radius),
+            0.5 + d.dot(bitangent) / (2.0 * radius),
+        )
+    }
+
+    // the boxes around the two end disks, enclosed together
+    fn bounding_box(&self) -> Aabb {
+        let disk = |center: Point3, radius: f64| {
+            let extent = |n: f64| radius * (1.0 - n * n).max(0.0).sqrt();
+            let e = Vec3(
+                extent(self.axis.x()),
+                extent(self.axis.y()),
+                extent(self.axis.z()),
+            );
+            Aabb::from_points(center - e, center + e)
+        };
+        Aabb::enclosing(
+            &disk(self.base, self.base_radius),
+            &disk(self.top(), self.top_radius),
+        )
+    }
+
+    // Fills rec with the nearest hit on the side or, if capped, on the end disks.
+    fn hit<'a>(
+        &self,
+        ray: &Ray,
+        ray_t: Interval,
+        caps: bool,
+        material: &'a Material,
+        rec: &mut HitRecord<'a>,
+    ) -> bool {
+        let side = self.hit_side(ray, ray_t);
+        let cap = if caps {
+            self.hit_caps(ray, Interval::new(ray_t.min, side.unwrap_or(ray_t.max)))
+        } else {
+            None
+        };
+
+        if let Some((t, normal)) = cap {
+            rec.t = t;
+            rec.point = ray.at(t);
+            rec.set_face_normal(ray, normal);
+            (rec.u, rec.v) = self.cap_uv(rec.point, normal);
+        } else if let Some(t) = side {
+            rec.t = t;
+            rec.point = ray.at(t);
+            rec.set_face_normal(ray, self.side_normal(rec.point));
+            (rec.u, rec.v) = self.side_uv(rec.point);
+        } else {
+            return false;
+        }
+        rec.material = material;
+        true
+    }
+}
+
+// A cylinder around the segment from base to top, closed with flat disks unless the
+// caps are turned off.
+pub struct Cylinder {
+    tube: Tube,
+    caps: bool,
+    material: Material,
+}
+
+impl Cylinder {
+    pub fn new(base: Point3, top: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            tube: Tube::new(base, top, radius, radius),
+            caps: true,
+            material,
+        }
+    }
+
+    pub fn with_caps(mut self, caps: bool) -> Self {
+        self.caps = caps;
+        self
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.tube.hit(ray, ray_t, self.caps, &self.material, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.tube.bounding_box()
+    }
+}
+
+// A cone with its base disk around base and its tip at top. A top radius truncates it
+// into a frustum, closed with a second disk if capped.
+pub struct Cone {
+    tube: Tube,
+    caps: bool,
+    material: Material,
+}
+
+impl Cone {
+    pub fn new(base: Point3, top: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            tube: Tube::new(base, top, radius, 0.0),
+            caps: true,
+            material,
+        }
+    }
+
+    pub fn with_top_radius(mut self, top_radius: f64) -> Self {
+        self.tube.top_radius = top_radius;
+        self
+    }
+
+    pub fn with_caps(mut self, caps: bool) -> Self {
+        self.caps = caps;
+        self
+    }
+}
+
+impl Hittable for Cone {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        self.tube.hit(ray, ray_t, self.caps, &self.material, rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.tube.bounding_box()
+    }
+}
+
+// A cylinder from base to top with a hemisphere on each end, i.e. every point within
+// radius of the segment. u is the angle around the axis, and v runs from 0 at the tip
+// of the base hemisphere to 1 at the tip of the top one.
+pub struct Capsule {
+    tube: Tube,
+    radius: f64,
+    material: Material,
+}
+
+impl Capsule {
+    pub fn new(base: Point3, top: Point3, radius: f64, material: Material) -> Self {
+        Self {
+            tube: Tube::new(base, top, radius, radius),
+            radius,
+            material,
+        }
+    }
+
+    // The nearest root in ray_t on the hemisphere around center that faces away from the
+    // body, i.e. towards outward along the axis.
+    fn hit_end(&self, ray: &Ray, ray_t: Interval, center: Point3, outward: Vec3) -> Option<f64> {
+        let oc = center - ray.origin;
+        let a = ray.dir.length_squared();
+        let h = ray.dir.dot(oc);
+        let c = oc.length_squared() - self.radius * self.radius;
+        let discriminant = h * h - a * c;
+
+        if discriminant < 0.0 {
+            return None;
+        }
+
+        let sqrtd = discriminant.sqrt();
+        [(h - sqrtd) / a, (h + sqrtd) / a]
+            .iter()
+            .copied()
+            .find(|&root| ray_t.surrounds(root) && (ray.at(root) - center).dot(outward) >= 0.0)
+    }
+}
+
+impl Hittable for Capsule {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let tube = &self.tube;
+        let mut closest = ray_t.max;
+        // the nearest hit so far, with the center its normal points away from
+        let mut hit = None;
+        if let Some(t) = tube.hit_side(ray, ray_t) {
+            closest = t;
+            let point = ray.at(t);
+            let along = (point - tube.base).dot(tube.axis);
+            hit = Some((t, tube.base + tube.axis * along));
+        }
+        for &(center, outward) in [(tube.base, -tube.axis), (tube.top(), tube.axis)].iter() {
+            if let Some(t) = self.hit_end(ray, Interval::new(ray_t.min, closest), center, outward) {
+                closest = t;
+                hit = Some((t, center));
+            }
+        }
+
+        let (t, center) = match hit {
+            Some(hit) => hit,
+            None => return false,
+        };
+        rec.t = t;
+        rec.point = ray.at(t);
+        rec.set_face_normal(ray, (rec.point - center) / self.radius);
+        let d = rec.point - tube.base;
+        (rec.u, rec.v) = (
+            tube.angle(d),
+            ((d.dot(tube.axis) + self.radius) / (tube.height + 2.0 * self.radius)).clamp(0.0, 1.0),
+        );
+        rec.material = &self.material;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3(self.radius, self.radius, self.radius);
+        let top = self.tube.top();
+        Aabb::enclosing(
+            &Aabb::from_points(self.tube.base - r, self.tube.base + r),
+            &Aabb::from_points(top - r, top + r),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    // Fires rays in random directions from random points inside a closed shape, given as
+    // its signed distance. Every ray has to leave through the surface, hitting its back.
+    fn check_inside<H: Hittable>(shape: &H, distance: impl Fn(Point3) -> f64, seed: u64) {
+        let bbox = shape.bounding_box();
+        let mut rng = seeded_rng(seed);
+        let mut rays = 0;
+        while rays < 2000 {
+            let origin = Vec3(
+                random_double_with_range(&mut rng, bbox.x.min, bbox.x.max),
+                random_double_with_range(&mut rng, bbox.y.min, bbox.y.max),
+                random_double_with_range(&mut rng, bbox.z.min, bbox.z.max),
+            );
+            if distance(origin) > -1e-3 {
+                continue;
+            }
+            rays += 1;
+            let ray = Ray::new(origin, random_unit_vector(&mut rng));
+            let mut rec = HitRecord::new();
+            assert!(
+                shape.hit(&ray, Interval::new(0.0, f64::INFINITY), &mut rec),
+                "no hit from {:?} towards {:?}",
+                origin,
+                ray.dir
+            );
+            assert!(!rec.front_face, "front face hit from {:?}", origin);
+            assert!(distance(rec.point).abs() < 1e-9, "hit {:?}", rec.point);
+            // the normal faces the ray, so back into the shape
+            assert!(rec.normal.dot(ray.dir) < 0.0);
+            assert!(distance(rec.point + rec.normal * 1e-4) < 0.0);
+            assert!(distance(rec.point - rec.normal * 1e-4) > 0.0);
+            assert!((0.0..=1.0).contains(&rec.u) && (0.0..=1.0).contains(&rec.v));
+        }
+    }
+
+    fn base() -> Point3 {
+        Vec3(0.2, -0.3, 0.1)
+    }
+
+    fn top() -> Point3 {
+        Vec3(-0.3, 0.6, 0.4)
+    }
+
+    // where p is along the axis from base to top (0..1 between them), and how far from it
+    fn axial(p: Point3) -> (f64, f64) {
+        let axis = top() - base();
+        let along = (p - base()).dot(axis) / axis.length_squared();
+        (along, (p - base() - axis * along).length())
+    }
+
+    // The signed distance to a capped frustum, exact enough near its surface.
+    fn frustum(base_radius: f64, top_radius: f64) -> impl Fn(Point3) -> f64 {
+        move |p| {
+            let height = (top() - base()).length();
+            let (along, radial) = axial(p);
+            let slope = (top_radius - base_radius) / height;
+            let radius = base_radius + (top_radius - base_radius) * along;
+            let side = (radial - radius) / (1.0 + slope * slope).sqrt();
+            let caps = (along - 0.5).abs() * height - height / 2.0;
+            side.max(caps)
+        }
+    }
+
+    #[test]
+    fn test_cylinder_inside() {
+        let cylinder = Cylinder::new(base(), top(), 0.3, Material::None);
+        check_inside(&cylinder, frustum(0.3, 0.3), 1);
+    }
+
+    #[test]
+    fn test_cone_inside() {
+        let cone = Cone::new(base(), top(), 0.4, Material::None).with_top_radius(0.15);
+        check_inside(&cone, frustum(0.4, 0.15), 2);
+    }
+
+    #[test]
+    fn test_capsule_inside() {
+        let capsule = Capsule::new(base(), top(), 0.25, Material::None);
+        check_inside(
+            &capsule,
+            |p| {
+                let axis = top() - base();
+                let along = ((p - base()).dot(axis) / axis.length_squared()).clamp(0.0, 1.0);
+                (p - base() - axis * along).length() - 0.25
+            },
+            3,
+        );
+    }
+
+    fn uv_at(shape: &dyn Hittable, origin: Point3, dir: Vec3) -> (f64, f64) {
+        let mut rec = HitRecord::new();
+        assert!(shape.hit(
+            &Ray::new(origin, dir),
+            Interval::new(0.001, f64::INFINITY),
+            &mut rec
+        ));
+        (rec.u, rec.v)
+    }
+
+    #[test]
+    fn test_uv() {
+        // upright, so the angle u starts from -x and turns towards +z
+        let cylinder = Cylinder::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 2.0, 0.0),
+            0.5,
+            Material::None,
+        );
+        assert_eq!(
+            uv_at(&cylinder, Vec3(5.0, 0.5, 0.0), Vec3(-1.0, 0.0, 0.0)),
+            (0.5, 0.25)
+        );
+        assert_eq!(
+            uv_at(&cylinder, Vec3(0.0, 1.0, 5.0), Vec3(0.0, 0.0, -1.0)),
+            (0.25, 0.5)
+        );
+        let (u, v) = uv_at(&cylinder, Vec3(0.0, 1.5, -5.0), Vec3(0.0, 0.0, 1.0));
+        assert!((u - 0.75).abs() < 1e-12 && v == 0.75);
+        // the top cap, across its diameter
+        let (u, v) = uv_at(&cylinder, Vec3(0.25, 5.0, 0.0), Vec3(0.0, -1.0, 0.0));
+        assert!((u - 0.75).abs() < 1e-12 && (v - 0.5).abs() < 1e-12);
+
+        // v runs over the rounded ends of a capsule too
+        let capsule = Capsule::new(
+            Vec3(0.0, 0.0, 0.0),
+            Vec3(0.0, 2.0, 0.0),
+            0.5,
+            Material::None,
+        );
+        assert_eq!(
+            uv_at(&capsule, Vec3(0.0, 5.0, 0.0), Vec3(0.0, -1.0, 0.0)).1,
+            1.0
+        );
+        assert_eq!(
+            uv_at(&capsule, Vec3(0.0, -5.0, 0.0), Vec3(0.0, 1.0, 0.0)).1,
+            0.0
+        );
+        let (_, v) = uv_at(&capsule, Vec3(5.0, 1.0, 0.0), Vec3(-1.0, 0.0, 0.0));
+        assert!((v - 0.5).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,440 @@
+use crate::utils::*;
+use crate::vec3::*;
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// Where the random numbers for each pixel sample come from. Scene files name them like
+// NAMES does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    // independent uniform random numbers
+    Random,
+    // jittered grid, with the strata of every dimension pair shuffled independently
+    Stratified,
+    // Halton sequence, randomized per pixel with a Cranley-Patterson rotation
+    Halton,
+    // 2D Sobol points with Owen scrambling, shuffled per dimension pair
+    Sobol,
+}
+
+impl SamplerKind {
+    pub const NAMES: [&'static str; 4] = ["random", "stratified", "halton", "sobol"];
+
+    pub fn from_name(name: &str) -> Option<SamplerKind> {
+        match name {
+            "random" => Some(SamplerKind::Random),
+            "stratified" => Some(SamplerKind::Stratified),
+            "halton" => Some(SamplerKind::Halton),
+            "sobol" => Some(SamplerKind::Sobol),
+            _ => None,
+        }
+    }
+}
+
+// the bases of the Halton dimensions; later dimensions fall back to random numbers
+const PRIMES: [u32; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131, 137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193,
+    197, 199, 211, 223, 227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307,
+    311,
+];
+
+// The sample values for one pixel. Every sample starts at dimension 0, and each call
+// to next_1d or next_2d moves on to the next dimension(s), so the camera and every bounce
+// draw from their own dimensions of the sequence.
+pub struct Sampler {
+    kind: SamplerKind,
+    samples_per_pixel: u32,
+    // how many samples the stratified sampler spreads over one grid
+    strata: u32,
+    pixel_seed: u64,
+    sample_index: u32,
+    dimension: u32,
+    rng: RenderRng,
+}
+
+impl Sampler {
+    pub fn new(kind: SamplerKind, samples_per_pixel: i32, seed: u64, i: i32, j: i32) -> Self {
+        Self {
+            kind,
+            samples_per_pixel: samples_per_pixel.max(1) as u32,
+            strata: samples_per_pixel.max(1) as u32,
+            pixel_seed: pixel_hash(seed, i, j),
+            sample_index: 0,
+            dimension: 0,
+            rng: pixel_rng(seed, i, j),
+        }
+    }
+
+    // Makes the stratified sampler cover a new grid of about strata cells with every
+    // strata samples, instead of one grid over all samples_per_pixel of them, so a pixel
+    // can stop after any complete grid.
+    pub fn with_strata(mut self, strata: i32) -> Self {
+        self.strata = (strata.max(1) as u32).min(self.samples_per_pixel);
+        self
+    }
+
+    // Whether stopping after this many samples leaves every grid of the stratified
+    // sampler complete. Always true for the other samplers.
+    pub fn is_stratum_boundary(&self, samples: i32) -> bool {
+        if self.kind != SamplerKind::Stratified {
+            return true;
+        }
+        let cells = self.cells();
+        let samples = samples as u32;
+        samples % cells == 0 || samples >= self.samples_per_pixel / cells * cells
+    }
+
+    pub fn start_sample(&mut self, index: i32) {
+        self.sample_index = index as u32;
+        self.dimension = 0;
+    }
+
+    // A value in [0, 1).
+    pub fn next_1d(&mut self) -> f64 {
+        match self.kind {
+            SamplerKind::Random => random_double(&mut self.rng),
+            SamplerKind::Halton => {
+                let dimension = self.take_dimensions(1);
+                self.halton(dimension)
+            }
+            // The 1D cases use the first coordinate of a 2D pattern.
+            SamplerKind::Stratified => self.next_2d().0,
+            SamplerKind::Sobol => self.next_2d().0,
+        }
+    }
+
+    // A point in [0, 1)^2.
+    pub fn next_2d(&mut self) -> (f64, f64) {
+        match self.kind {
+            SamplerKind::Random => (random_double(&mut self.rng), random_double(&mut self.rng)),
+            SamplerKind::Halton => {
+                let dimension = self.take_dimensions(2);
+                (self.halton(dimension), self.halton(dimension + 1))
+            }
+            SamplerKind::Stratified => {
+                let dimension = self.take_dimensions(2);
+                self.stratified(dimension)
+            }
+            SamplerKind::Sobol => {
+                let dimension = self.take_dimensions(2);
+                self.sobol(dimension)
+            }
+        }
+    }
+
+    // A uniformly distributed direction.
+    pub fn unit_vector(&mut self) -> Vec3 {
+        if self.kind == SamplerKind::Random {
+            return random_unit_vector(&mut self.rng);
+        }
+        let (u1, u2) = self.next_2d();
+        let z = 1.0 - 2.0 * u1;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u2;
+        Vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // A uniformly distributed point in the unit disk on the xy plane.
+    pub fn in_unit_disk(&mut self) -> Vec3 {
+        if self.kind == SamplerKind::Random {
+            return random_in_unit_disk(&mut self.rng);
+        }
+        let (u1, u2) = self.next_2d();
+        let r = u1.sqrt();
+        let theta = 2.0 * PI * u2;
+        Vec3(r * theta.cos(), r * theta.sin(), 0.0)
+    }
+
+    fn take_dimensions(&mut self, count: u32) -> u32 {
+        let dimension = self.dimension;
+        self.dimension += count;
+        dimension
+    }
+
+    // a hash of this pixel and a dimension, for per-dimension scrambling
+    fn dimension_seed(&self, dimension: u32) -> u32 {
+        (mix(self.pixel_seed ^ (dimension as u64).wrapping_mul(0x9e3779b97f4a7c15)) >> 32) as u32
+    }
+
+    fn halton(&mut self, dimension: u32) -> f64 {
+        match PRIMES.get(dimension as usize) {
+            Some(&base) => {
+                let offset = self.dimension_seed(dimension) as f64 / 4294967296.0;
+                let value = radical_inverse(base, self.sample_index) + offset;
+                value - value.floor()
+            }
+            None => random_double(&mut self.rng),
+        }
+    }
+
+    // The largest nx by ny grid with no more cells than strata.
+    fn grid(&self) -> (u32, u32) {
+        let nx = (self.strata as f64).sqrt() as u32;
+        (nx, self.strata / nx)
+    }
+
+    fn cells(&self) -> u32 {
+        let (nx, ny) = self.grid();
+        nx * ny
+    }
+
+    fn stratified(&mut self, dimension: u32) -> (f64, f64) {
+        // Each run of nx * ny samples covers a grid of its own. Samples after the last
+        // complete grid are plain random, so every cell is still sampled exactly as often
+        // as the others.
+        let (nx, ny) = self.grid();
+        let cells = nx * ny;
+        if self.sample_index >= self.samples_per_pixel / cells * cells {
+            return (random_double(&mut self.rng), random_double(&mut self.rng));
+        }
+
+        let pass = self.sample_index / cells;
+        let seed = hash_u32(self.dimension_seed(dimension), pass);
+        let stratum = permute(self.sample_index % cells, cells, seed);
+        let (x, y) = (stratum % nx, stratum / nx);
+        let jitter_x = random_double(&mut self.rng);
+        let jitter_y = random_double(&mut self.rng);
+        (
+            (x as f64 + jitter_x) / nx as f64,
+            ((y as f64 + jitter_y) / ny as f64).min(ONE_MINUS_EPSILON),
+        )
+    }
+
+    fn sobol(&self, dimension: u32) -> (f64, f64) {
+        // Shuffling the index per dimension pair decorrelates the pairs from each other.
+        let seed = self.dimension_seed(dimension);
+        let index = nested_uniform_scramble(self.sample_index, seed);
+        let x = nested_uniform_scramble(sobol_dimension_0(index), hash_u32(seed, 1));
+        let y = nested_uniform_scramble(sobol_dimension_1(index), hash_u32(seed, 2));
+        (to_unit(x), to_unit(y))
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+fn to_unit(x: u32) -> f64 {
+    (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+fn hash_u32(seed: u32, salt: u32) -> u32 {
+    (mix(((seed as u64) << 32) | salt as u64) >> 32) as u32
+}
+
+// index written in base, mirrored around the decimal point
+fn radical_inverse(base: u32, mut index: u32) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut factor = inverse_base;
+    let mut value = 0.0;
+    while index > 0 {
+        value += (index % base) as f64 * factor;
+        index /= base;
+        factor *= inverse_base;
+    }
+    value.min(ONE_MINUS_EPSILON)
+}
+
+// The first Sobol dimension is the van der Corput sequence.
+fn sobol_dimension_0(index: u32) -> u32 {
+    index.reverse_bits()
+}
+
+// The second Sobol dimension, whose direction numbers are v[i] = v[i-1] ^ (v[i-1] >> 1).
+fn sobol_dimension_1(mut index: u32) -> u32 {
+    let mut result = 0;
+    let mut v = 1u32 << 31;
+    while index != 0 {
+        if index & 1 != 0 {
+            result ^= v;
+        }
+        index >>= 1;
+        v ^= v >> 1;
+    }
+    result
+}
+
+// Laine-Karras style hash that only lets lower bits affect higher ones.
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+// Owen scrambling (Burley, "Practical Hash-based Owen Scrambling", 2020).
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Element i of a random permutation of 0..length chosen by seed
+// (Kensler, "Correlated Multi-Jittered Sampling", 2013).
+fn permute(mut i: u32, length: u32, seed: u32) -> u32 {
+    let mut w = length - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < length {
+            break;
+        }
+    }
+    i.wrapping_add(seed) % length
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Random,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    #[test]
+    fn test_names() {
+        for (&name, &kind) in SamplerKind::NAMES.iter().zip(KINDS.iter()) {
+            assert_eq!(SamplerKind::from_name(name), Some(kind));
+        }
+        assert_eq!(SamplerKind::from_name("jittered"), None);
+    }
+
+    #[test]
+    fn test_range() {
+        // Halton runs out of primes after 64 dimensions and falls back to random numbers.
+        for &kind in KINDS.iter() {
+            for &samples_per_pixel in [1, 7, 16, 100].iter() {
+                let mut sampler = Sampler::new(kind, samples_per_pixel, 9, 3, 4);
+                for index in 0..samples_per_pixel {
+                    sampler.start_sample(index);
+                    for _ in 0..40 {
+                        let x = sampler.next_1d();
+                        let (y, z) = sampler.next_2d();
+                        for value in [x, y, z] {
+                            assert!((0.0..1.0).contains(&value), "{:?}: {}", kind, value);
+                        }
+                    }
+                    assert!((sampler.unit_vector().length() - 1.0).abs() < 1e-12);
+                    assert!(sampler.in_unit_disk().length() <= 1.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_stratified_strata() {
+        // 16 is a 4 by 4 grid, 12 a 3 by 4 one, and 10 a 3 by 3 one plus a random sample
+        for &(samples_per_pixel, nx, ny) in [(16, 4, 4), (12, 3, 4), (10, 3, 3)].iter() {
+            for &(i, j) in [(0, 0), (5, 9)].iter() {
+                let mut sampler = Sampler::new(SamplerKind::Stratified, samples_per_pixel, 1, i, j);
+                let mut strata = vec![HashSet::new(); 3];
+                for index in 0..nx * ny {
+                    sampler.start_sample(index);
+                    for dimension in strata.iter_mut() {
+                        let (x, y) = sampler.next_2d();
+                        let cell = ((x * nx as f64) as i32, (y * ny as f64) as i32);
+                        assert!(cell.0 < nx && cell.1 < ny);
+                        assert!(dimension.insert(cell), "cell {:?} sampled twice", cell);
+                    }
+                }
+                assert!(strata.iter().all(|cells| cells.len() == (nx * ny) as usize));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stratified_passes() {
+        // 40 samples in grids of 9, then 4 random ones
+        let mut sampler = Sampler::new(SamplerKind::Stratified, 40, 2, 1, 1).with_strata(10);
+        for pass in 0..4 {
+            let mut cells = HashSet::new();
+            for index in pass * 9..(pass + 1) * 9 {
+                sampler.start_sample(index);
+                let (x, y) = sampler.next_2d();
+                cells.insert(((x * 3.0) as u32, (y * 3.0) as u32));
+            }
+            assert_eq!(cells.len(), 9);
+        }
+        let boundaries: Vec<i32> = (1..=40)
+            .filter(|&n| sampler.is_stratum_boundary(n))
+            .collect();
+        assert_eq!(boundaries, vec![9, 18, 27, 36, 37, 38, 39, 40]);
+        let sobol = Sampler::new(SamplerKind::Sobol, 40, 2, 1, 1).with_strata(10);
+        assert!((1..=40).all(|n| sobol.is_stratum_boundary(n)));
+    }
+
+    #[test]
+    fn test_sobol_sequence() {
+        let first = [0.0, 0.5, 0.25, 0.75, 0.125, 0.625, 0.375, 0.875];
+        let second = [0.0, 0.5, 0.75, 0.25, 0.625, 0.125, 0.375, 0.875];
+        for index in 0..8 {
+            assert_eq!(to_unit(sobol_dimension_0(index)), first[index as usize]);
+            assert_eq!(to_unit(sobol_dimension_1(index)), second[index as usize]);
+        }
+        // Every power of two prefix is stratified in both dimensions together.
+        let cells: HashSet<(u32, u32)> = (0..256)
+            .map(|i| (sobol_dimension_0(i) >> 28, sobol_dimension_1(i) >> 28))
+            .collect();
+        assert_eq!(cells.len(), 256);
+    }
+
+    #[test]
+    fn test_scramble_is_bijective() {
+        for &seed in [0, 1, 0xdeadbeef].iter() {
+            // The low bits of the hash only depend on the low bits of its input, and the
+            // high bits of the scramble on the high bits of its input. Both permute them.
+            let low: HashSet<u32> = (0..1 << 16)
+                .map(|x| laine_karras_permutation(x, seed) & 0xffff)
+                .collect();
+            assert_eq!(low.len(), 1 << 16);
+            let high: HashSet<u32> = (0..1 << 16)
+                .map(|x| nested_uniform_scramble(x << 16, seed) >> 16)
+                .collect();
+            assert_eq!(high.len(), 1 << 16);
+
+            // the scrambled points stay stratified
+            let cells: HashSet<(u32, u32)> = (0..256)
+                .map(|i| {
+                    let index = nested_uniform_scramble(i, seed);
+                    (
+                        nested_uniform_scramble(sobol_dimension_0(index), seed) >> 28,
+                        nested_uniform_scramble(sobol_dimension_1(index), seed ^ 1) >> 28,
+                    )
+                })
+                .collect();
+            assert_eq!(cells.len(), 256);
+
+            for &length in [1, 2, 9, 16, 100].iter() {
+                let permuted: HashSet<u32> =
+                    (0..length).map(|i| permute(i, length, seed)).collect();
+                assert_eq!(permuted.len(), length as usize);
+                assert!(permuted.iter().all(|&i| i < length));
+            }
+        }
+    }
+}
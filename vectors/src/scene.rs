@@ -0,0 +1,1313 @@
+use crate::camera::*;
+use crate::cuboid::*;
+use crate::hittable::*;
+use crate::image::*;
+use crate::material::*;
+use crate::obj::*;
+use crate::planar::*;
+use crate::quadric::*;
+use crate::sampler::*;
+use crate::texture::*;
+use crate::torus::*;
+use crate::triangle::*;
+use crate::vec3::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Scene description files, written in TOML:
+//
+//   [camera]
+//   image_width = 400
+//   look_from = [-2.0, 2.0, 1.0]
+//   background = { type = "gradient", bottom = [1.0, 1.0, 1.0], top = [0.5, 0.7, 1.0] }
+//
+//   [textures.checker]
+//   type = "checker"
+//   scale = 0.5
+//   even = [0.2, 0.3, 0.1]
+//   odd = [0.9, 0.9, 0.9]
+//
+//   [materials.ground]
+//   type = "lambertian"
+//   albedo = "checker"   # or a color, e.g. [0.8, 0.8, 0.0]
+//
+//   [[objects]]
+//   type = "sphere"
+//   center = [0.0, -100.5, -1.0]
+//   radius = 100.0
+//   material = "ground"
+//
+// Every camera field is optional and falls back to the defaults below.
+// Paths inside the scene (e.g. obj models, image textures) are resolved relative to the scene file.
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    // the file is not valid TOML or doesn't match the expected layout
+    Parse {
+        path: PathBuf,
+        message: String,
+    },
+    // the file parsed, but a value in it can't be used
+    Invalid {
+        path: PathBuf,
+        field: String,
+        message: String,
+    },
+    Obj(ObjError),
+    Image(ImageError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse { path, message } => write!(f, "{}: {}", path.display(), message),
+            SceneError::Invalid {
+                path,
+                field,
+                message,
+            } => write!(f, "{}: {}: {}", path.display(), field, message),
+            SceneError::Obj(err) => write!(f, "{}", err),
+            SceneError::Image(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Obj(err) => Some(err),
+            SceneError::Image(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(err: ObjError) -> Self {
+        SceneError::Obj(err)
+    }
+}
+
+impl From<ImageError> for SceneError {
+    fn from(err: ImageError) -> Self {
+        SceneError::Image(err)
+    }
+}
+
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_scene(&source, path)
+}
+
+// Parses scene source text. path is used for error messages and to resolve relative paths.
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let description: SceneDescription =
+        toml::from_str(source).map_err(|err| SceneError::Parse {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+    let builder = SceneBuilder {
+        path,
+        base_dir: path.parent().unwrap_or_else(|| Path::new("")),
+    };
+    builder.build(description)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    #[serde(default)]
+    camera: CameraDescription,
+    #[serde(default)]
+    textures: BTreeMap<String, TextureDescription>,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDescription>,
+    #[serde(default)]
+    objects: Vec<ObjectDescription>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct CameraDescription {
+    aspect_ratio: f64,
+    image_width: i32,
+    samples_per_pixel: i32,
+    min_samples_per_pixel: i32,
+    // 0 disables adaptive sampling
+    noise_threshold: f64,
+    max_depth: i32,
+    vfov: f64,
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    vup: [f64; 3],
+    defocus_angle: f64,
+    // defaults to the distance between look_from and look_at
+    focus_dist: Option<f64>,
+    // defaults to the available cores
+    threads: Option<usize>,
+    background: BackgroundDescription,
+    seed: u64,
+    sampler: SamplerKind,
+}
+
+impl Default for CameraDescription {
+    fn default() -> Self {
+        Self {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            samples_per_pixel: 100,
+            min_samples_per_pixel: 16,
+            noise_threshold: 0.0,
+            max_depth: 50,
+            vfov: 90.0,
+            look_from: [0.0, 0.0, 0.0],
+            look_at: [0.0, 0.0, -1.0],
+            vup: [0.0, 1.0, 0.0],
+            defocus_angle: 0.0,
+            focus_dist: None,
+            threads: None,
+            background: BackgroundDescription::Sky,
+            seed: 0,
+            sampler: SamplerKind::Random,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDescription {
+    Sky,
+    Solid { color: [f64; 3] },
+    Gradient { bottom: [f64; 3], top: [f64; 3] },
+    None,
+}
+
+// A constant color, or the name of an entry in [textures].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TextureReference {
+    Color([f64; 3]),
+    Name(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDescription {
+    Solid {
+        color: [f64; 3],
+    },
+    Checker {
+        scale: f64,
+        even: TextureReference,
+        odd: TextureReference,
+    },
+    UvChecker {
+        columns: f64,
+        rows: f64,
+        even: TextureReference,
+        odd: TextureReference,
+    },
+    // a PNG or PPM file; colors are decoded from sRGB unless srgb = false
+    Image {
+        path: PathBuf,
+        wrap: Option<WrapDescription>,
+        filter: Option<FilterDescription>,
+        srgb: Option<bool>,
+    },
+    Turbulence(NoiseDescription),
+    Marble(NoiseDescription),
+    Wood(NoiseDescription),
+}
+
+// Perlin noise patterns, blending between the colors low and high.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseDescription {
+    scale: f64,
+    #[serde(default)]
+    seed: u64,
+    low: Option<[f64; 3]>,
+    high: Option<[f64; 3]>,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum WrapDescription {
+    Repeat,
+    Clamp,
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum FilterDescription {
+    Nearest,
+    Bilinear,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Lambertian { albedo: TextureReference },
+    Metal { albedo: TextureReference, fuzz: f64 },
+    Dielectric { refraction_index: f64 },
+    DiffuseLight { emit: [f64; 3] },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    // the half on the side cut points to, closed with a flat disk if cap is true
+    HalfSphere {
+        center: [f64; 3],
+        radius: f64,
+        cut: [f64; 3],
+        #[serde(default)]
+        cap: bool,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        material: String,
+    },
+    // a parallelogram with edges u and v leaving corner
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    // an infinite plane, e.g. a floor
+    Plane {
+        point: [f64; 3],
+        normal: [f64; 3],
+        material: String,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    // an axis-aligned box from min to max, optionally rotated around its center
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: String,
+        // materials for single faces, replacing material on them
+        #[serde(default)]
+        faces: BoxFaces,
+        rotation: Option<RotationDescription>,
+    },
+    // closed with flat disks at both ends unless caps is false
+    Cylinder {
+        base: [f64; 3],
+        top: [f64; 3],
+        radius: f64,
+        #[serde(default = "capped")]
+        caps: bool,
+        material: String,
+    },
+    // pointed at top, or cut off there with a disk of top_radius
+    Cone {
+        base: [f64; 3],
+        top: [f64; 3],
+        radius: f64,
+        #[serde(default)]
+        top_radius: f64,
+        #[serde(default = "capped")]
+        caps: bool,
+        material: String,
+    },
+    // a cylinder with hemispheres on both ends
+    Capsule {
+        base: [f64; 3],
+        top: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    // a ring around axis, by default lying flat
+    Torus {
+        center: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        axis: Option<[f64; 3]>,
+        material: String,
+    },
+    // a Wavefront .obj model; material is used for faces without a usemtl material
+    Obj {
+        path: PathBuf,
+        material: Option<String>,
+    },
+}
+
+// The faces are named as seen from +z, before the box is rotated.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct BoxFaces {
+    left: Option<String>,
+    right: Option<String>,
+    bottom: Option<String>,
+    top: Option<String>,
+    back: Option<String>,
+    front: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationDescription {
+    axis: [f64; 3],
+    degrees: f64,
+}
+
+fn capped() -> bool {
+    true
+}
+
+// Whether x is a usable positive or non-negative number. NaN fails every comparison, so
+// a plain x <= 0.0 check lets it through, and infinities break the geometry.
+fn is_positive(x: f64) -> bool {
+    x > 0.0 && x.is_finite()
+}
+
+fn is_non_negative(x: f64) -> bool {
+    x >= 0.0 && x.is_finite()
+}
+
+fn vec3(v: [f64; 3]) -> Vec3 {
+    Vec3(v[0], v[1], v[2])
+}
+
+// The [textures] table while it's being built. Textures are built on first use, since
+// checkers can refer to other textures by name.
+struct Textures<'a> {
+    descriptions: &'a BTreeMap<String, TextureDescription>,
+    built: BTreeMap<String, Texture>,
+    // names currently being built, to catch textures that refer to themselves
+    resolving: Vec<String>,
+}
+
+struct SceneBuilder<'a> {
+    path: &'a Path,
+    base_dir: &'a Path,
+}
+
+impl SceneBuilder<'_> {
+    fn invalid<F: Into<String>, M: Into<String>>(&self, field: F, message: M) -> SceneError {
+        SceneError::Invalid {
+            path: self.path.to_path_buf(),
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    fn build(&self, description: SceneDescription) -> Result<Scene, SceneError> {
+        let camera = self.camera(description.camera)?;
+
+        let mut textures = Textures {
+            descriptions: &description.textures,
+            built: BTreeMap::new(),
+            resolving: Vec::new(),
+        };
+        for name in description.textures.keys() {
+            self.named_texture(name, &mut textures)?;
+        }
+
+        let mut materials = BTreeMap::new();
+        for (name, material) in description.materials {
+            let field = format!("materials.{}", name);
+            let material = self.material(&field, material, &mut textures)?;
+            materials.insert(name, material);
+        }
+
+        let mut objects = Vec::new();
+        for (index, object) in description.objects.into_iter().enumerate() {
+            let field = format!("objects[{}]", index);
+            self.object(&field, object, &materials, &mut objects)?;
+        }
+
+        Ok(Scene {
+            camera,
+            world: HittableList { objects },
+        })
+    }
+
+    fn camera(&self, description: CameraDescription) -> Result<Camera, SceneError> {
+        if !is_positive(description.aspect_ratio) {
+            return Err(self.invalid("camera.aspect_ratio", "must be positive"));
+        }
+        if description.image_width < 1 {
+            return Err(self.invalid("camera.image_width", "must be at least 1"));
+        }
+        if description.samples_per_pixel < 1 {
+            return Err(self.invalid("camera.samples_per_pixel", "must be at least 1"));
+        }
+        if description.min_samples_per_pixel < 1 {
+            return Err(self.invalid("camera.min_samples_per_pixel", "must be at least 1"));
+        }
+        if !is_non_negative(description.noise_threshold) {
+            return Err(self.invalid("camera.noise_threshold", "must not be negative"));
+        }
+        if description.threads == Some(0) {
+            return Err(self.invalid("camera.threads", "must be at least 1"));
+        }
+        if !(is_positive(description.vfov) && description.vfov < 180.0) {
+            return Err(self.invalid("camera.vfov", "must be between 0 and 180 degrees"));
+        }
+        if !(is_non_negative(description.defocus_angle) && description.defocus_angle < 180.0) {
+            return Err(self.invalid(
+                "camera.defocus_angle",
+                "must be at least 0 and less than 180 degrees",
+            ));
+        }
+        if description
+            .focus_dist
+            .is_some_and(|focus_dist| !is_positive(focus_dist))
+        {
+            return Err(self.invalid("camera.focus_dist", "must be positive"));
+        }
+        let vectors = [
+            ("camera.look_from", description.look_from),
+            ("camera.look_at", description.look_at),
+            ("camera.vup", description.vup),
+        ];
+        for &(field, v) in vectors.iter() {
+            if !v.iter().all(|x| x.is_finite()) {
+                return Err(self.invalid(field, "must be finite"));
+            }
+        }
+        // the camera's frame is built from these, and degenerates without a view direction
+        // or with an up vector along it
+        let view = vec3(description.look_from) - vec3(description.look_at);
+        if !is_positive(view.length_squared()) {
+            return Err(self.invalid("camera.look_at", "must differ from look_from"));
+        }
+        if !is_positive(vec3(description.vup).cross(view).length_squared()) {
+            return Err(self.invalid(
+                "camera.vup",
+                "must not be zero or parallel to the view direction",
+            ));
+        }
+
+        let mut camera = Camera::new(
+            description.aspect_ratio,
+            description.vfov,
+            description.image_width,
+            description.samples_per_pixel,
+            description.max_depth,
+            vec3(description.look_from),
+            vec3(description.look_at),
+            vec3(description.vup),
+        );
+        camera.min_samples_per_pixel = description.min_samples_per_pixel;
+        camera.noise_threshold = description.noise_threshold;
+        camera.defocus_angle = description.defocus_angle;
+        if let Some(focus_dist) = description.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(threads) = description.threads {
+            camera.threads = threads;
+        }
+        camera.seed = description.seed;
+        camera.sampler = description.sampler;
+        camera.background = match description.background {
+            BackgroundDescription::Sky => Background::SKY,
+            BackgroundDescription::Solid { color } => Background::Solid(vec3(color)),
+            BackgroundDescription::Gradient { bottom, top } => Background::Gradient {
+                bottom: vec3(bottom),
+                top: vec3(top),
+            },
+            BackgroundDescription::None => Background::None,
+        };
+        Ok(camera)
+    }
+
+    // Builds the texture called name, and first any textures it refers to.
+    fn named_texture(&self, name: &str, textures: &mut Textures) -> Result<Texture, SceneError> {
+        if let Some(texture) = textures.built.get(name) {
+            return Ok(texture.clone());
+        }
+        let field = format!("textures.{}", name);
+        if textures.resolving.iter().any(|resolving| resolving == name) {
+            return Err(self.invalid(field, "refers back to itself"));
+        }
+
+        textures.resolving.push(name.to_string());
+        let texture = self.texture(&field, &textures.descriptions[name], textures)?;
+        textures.resolving.pop();
+        textures.built.insert(name.to_string(), texture.clone());
+        Ok(texture)
+    }
+
+    fn texture(
+        &self,
+        field: &str,
+        description: &TextureDescription,
+        textures: &mut Textures,
+    ) -> Result<Texture, SceneError> {
+        Ok(match description {
+            TextureDescription::Solid { color } => Texture::Solid(vec3(*color)),
+            TextureDescription::Checker { scale, even, odd } => {
+                if !is_positive(*scale) {
+                    return Err(self.invalid(format!("{}.scale", field), "must be positive"));
+                }
+                let even = self.texture_reference(&format!("{}.even", field), even, textures)?;
+                let odd = self.texture_reference(&format!("{}.odd", field), odd, textures)?;
+                Texture::checker(*scale, even, odd)
+            }
+            TextureDescription::UvChecker {
+                columns,
+                rows,
+                even,
+                odd,
+            } => {
+                if !is_positive(*columns) {
+                    return Err(self.invalid(format!("{}.columns", field), "must be positive"));
+                }
+                if !is_positive(*rows) {
+                    return Err(self.invalid(format!("{}.rows", field), "must be positive"));
+                }
+                let even = self.texture_reference(&format!("{}.even", field), even, textures)?;
+                let odd = self.texture_reference(&format!("{}.odd", field), odd, textures)?;
+                Texture::uv_checker(*columns, *rows, even, odd)
+            }
+            TextureDescription::Image {
+                path,
+                wrap,
+                filter,
+                srgb,
+            } => {
+                let image = load_image(self.base_dir.join(path))?;
+                let texture = ImageTexture::from_decoded(image, srgb.unwrap_or(true))
+                    .with_wrap(match wrap {
+                        Some(WrapDescription::Clamp) => Wrap::Clamp,
+                        _ => Wrap::Repeat,
+                    })
+                    .with_filter(match filter {
+                        Some(FilterDescription::Nearest) => Filter::Nearest,
+                        _ => Filter::Bilinear,
+                    });
+                Texture::Image(Arc::new(texture))
+            }
+            TextureDescription::Turbulence(noise) => self.noise_texture(
+                field,
+                NoisePattern::Turbulence,
+                noise,
+                [[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]],
+            )?,
+            TextureDescription::Marble(noise) => self.noise_texture(
+                field,
+                NoisePattern::Marble,
+                noise,
+                [[0.1, 0.1, 0.1], [0.9, 0.9, 0.9]],
+            )?,
+            TextureDescription::Wood(noise) => self.noise_texture(
+                field,
+                NoisePattern::Wood,
+                noise,
+                [[0.7, 0.5, 0.28], [0.3, 0.16, 0.06]],
+            )?,
+        })
+    }
+
+    fn noise_texture(
+        &self,
+        field: &str,
+        pattern: NoisePattern,
+        description: &NoiseDescription,
+        [low, high]: [[f64; 3]; 2],
+    ) -> Result<Texture, SceneError> {
+        if !is_positive(description.scale) {
+            return Err(self.invalid(format!("{}.scale", field), "must be positive"));
+        }
+        Ok(Texture::noise(
+            pattern,
+            description.seed,
+            description.scale,
+            vec3(description.low.unwrap_or(low)),
+            vec3(description.high.unwrap_or(high)),
+        ))
+    }
+
+    fn texture_reference(
+        &self,
+        field: &str,
+        reference: &TextureReference,
+        textures: &mut Textures,
+    ) -> Result<Texture, SceneError> {
+        match reference {
+            TextureReference::Color(color) => Ok(Texture::Solid(vec3(*color))),
+            TextureReference::Name(name) => {
+                if !textures.descriptions.contains_key(name) {
+                    return Err(self.invalid(field, format!("unknown texture '{}'", name)));
+                }
+                self.named_texture(name, textures)
+            }
+        }
+    }
+
+    fn material(
+        &self,
+        field: &str,
+        description: MaterialDescription,
+        textures: &mut Textures,
+    ) -> Result<Material, SceneError> {
+        Ok(match description {
+            MaterialDescription::Lambertian { albedo } => {
+                let albedo =
+                    self.texture_reference(&format!("{}.albedo", field), &albedo, textures)?;
+                Material::Lambertian(albedo)
+            }
+            MaterialDescription::Metal { albedo, fuzz } => {
+                if !is_non_negative(fuzz) {
+                    return Err(self.invalid(format!("{}.fuzz", field), "must not be negative"));
+                }
+                let albedo =
+                    self.texture_reference(&format!("{}.albedo", field), &albedo, textures)?;
+                Material::new_metal(albedo, fuzz)
+            }
+            MaterialDescription::Dielectric { refraction_index } => {
+                if !is_positive(refraction_index) {
+                    return Err(
+                        self.invalid(format!("{}.refraction_index", field), "must be positive")
+                    );
+                }
+                Material::Dielectric(refraction_index)
+            }
+            MaterialDescription::DiffuseLight { emit } => Material::DiffuseLight(vec3(emit)),
+        })
+    }
+
+    // the checks shared by the shapes around a segment from base to top
+    fn check_axis(
+        &self,
+        field: &str,
+        base: [f64; 3],
+        top: [f64; 3],
+        radius: f64,
+    ) -> Result<(), SceneError> {
+        if !is_positive(radius) {
+            return Err(self.invalid(format!("{}.radius", field), "must be positive"));
+        }
+        if !is_positive((vec3(top) - vec3(base)).length_squared()) {
+            return Err(self.invalid(format!("{}.top", field), "must differ from base"));
+        }
+        Ok(())
+    }
+
+    fn lookup(
+        &self,
+        field: &str,
+        name: &str,
+        materials: &BTreeMap<String, Material>,
+    ) -> Result<Material, SceneError> {
+        materials.get(name).cloned().ok_or_else(|| {
+            self.invalid(
+                format!("{}.material", field),
+                format!("unknown material '{}'", name),
+            )
+        })
+    }
+
+    fn object(
+        &self,
+        field: &str,
+        description: ObjectDescription,
+        materials: &BTreeMap<String, Material>,
+        objects: &mut Vec<HittableEnum>,
+    ) -> Result<(), SceneError> {
+        match description {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => {
+                if !is_positive(radius) {
+                    return Err(self.invalid(format!("{}.radius", field), "must be positive"));
+                }
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::Sphere(Sphere::new(
+                    vec3(center),
+                    radius,
+                    material,
+                )));
+            }
+            ObjectDescription::HalfSphere {
+                center,
+                radius,
+                cut,
+                cap,
+                material,
+            } => {
+                if !is_positive(radius) {
+                    return Err(self.invalid(format!("{}.radius", field), "must be positive"));
+                }
+                if !is_positive(vec3(cut).length_squared()) {
+                    return Err(self.invalid(format!("{}.cut", field), "must not be zero"));
+                }
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::HalfSphere(
+                    HalfSphere::new(vec3(center), radius, material, vec3(cut)).with_cap(cap),
+                ));
+            }
+            ObjectDescription::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => {
+                let material = self.lookup(field, &material, materials)?;
+                let mut triangle = Triangle::new(
+                    vec3(vertices[0]),
+                    vec3(vertices[1]),
+                    vec3(vertices[2]),
+                    material,
+                );
+                if let Some(normals) = normals {
+                    triangle = triangle.with_normals(normals.map(vec3));
+                }
+                if let Some(uvs) = uvs {
+                    triangle = triangle.with_uvs(uvs.map(|uv| (uv[0], uv[1])));
+                }
+                objects.push(HittableEnum::Triangle(triangle));
+            }
+            ObjectDescription::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } => {
+                if !is_positive(vec3(u).cross(vec3(v)).length_squared()) {
+                    return Err(
+                        self.invalid(format!("{}.u", field), "must not be zero or parallel to v")
+                    );
+                }
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::Quad(Quad::new(
+                    vec3(corner),
+                    vec3(u),
+                    vec3(v),
+                    material,
+                )));
+            }
+            ObjectDescription::Plane {
+                point,
+                normal,
+                material,
+            } => {
+                if !is_positive(vec3(normal).length_squared()) {
+                    return Err(self.invalid(format!("{}.normal", field), "must not be zero"));
+                }
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::Plane(Plane::new(
+                    vec3(point),
+                    vec3(normal),
+                    material,
+                )));
+            }
+            ObjectDescription::Disk {
+                center,
+                normal,
+                radius,
+                material,
+            } => {
+                if !is_positive(radius) {
+                    return Err(self.invalid(format!("{}.radius", field), "must be positive"));
+                }
+                if !is_positive(vec3(normal).length_squared()) {
+                    return Err(self.invalid(format!("{}.normal", field), "must not be zero"));
+                }
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::Disk(Disk::new(
+                    vec3(center),
+                    vec3(normal),
+                    radius,
+                    material,
+                )));
+            }
+            ObjectDescription::Box {
+                min,
+                max,
+                material,
+                faces,
+                rotation,
+            } => {
+                if (0..3).any(|axis| !is_positive(max[axis] - min[axis])) {
+                    return Err(self.invalid(
+                        format!("{}.max", field),
+                        "must be greater than min on every axis",
+                    ));
+                }
+                let material = self.lookup(field, &material, materials)?;
+                let face = |index: usize, name: &Option<String>| match name {
+                    Some(name) => materials.get(name).cloned().ok_or_else(|| {
+                        self.invalid(
+                            format!("{}.faces.{}", field, FACE_NAMES[index]),
+                            format!("unknown material '{}'", name),
+                        )
+                    }),
+                    None => Ok(material.clone()),
+                };
+                let faces = [
+                    face(0, &faces.left)?,
+                    face(1, &faces.right)?,
+                    face(2, &faces.bottom)?,
+                    face(3, &faces.top)?,
+                    face(4, &faces.back)?,
+                    face(5, &faces.front)?,
+                ];
+                let mut cuboid = Cuboid::new(vec3(min), vec3(max), material).with_faces(faces);
+                if let Some(rotation) = rotation {
+                    if !is_positive(vec3(rotation.axis).length_squared()) {
+                        return Err(
+                            self.invalid(format!("{}.rotation.axis", field), "must not be zero")
+                        );
+                    }
+                    cuboid = cuboid.rotated(vec3(rotation.axis), rotation.degrees);
+                }
+                objects.push(HittableEnum::Cuboid(cuboid));
+            }
+            ObjectDescription::Cylinder {
+                base,
+                top,
+                radius,
+                caps,
+                material,
+            } => {
+                self.check_axis(field, base, top, radius)?;
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::Cylinder(
+                    Cylinder::new(vec3(base), vec3(top), radius, material).with_caps(caps),
+                ));
+            }
+            ObjectDescription::Cone {
+                base,
+                top,
+                radius,
+                top_radius,
+                caps,
+                material,
+            } => {
+                self.check_axis(field, base, top, radius)?;
+                if !is_non_negative(top_radius) {
+                    return Err(
+                        self.invalid(format!("{}.top_radius", field), "must not be negative")
+                    );
+                }
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::Cone(
+                    Cone::new(vec3(base), vec3(top), radius, material)
+                        .with_top_radius(top_radius)
+                        .with_caps(caps),
+                ));
+            }
+            ObjectDescription::Capsule {
+                base,
+                top,
+                radius,
+                material,
+            } => {
+                self.check_axis(field, base, top, radius)?;
+                let material = self.lookup(field, &material, materials)?;
+                objects.push(HittableEnum::Capsule(Capsule::new(
+                    vec3(base),
+                    vec3(top),
+                    radius,
+                    material,
+                )));
+            }
+            ObjectDescription::Torus {
+                center,
+                major_radius,
+                minor_radius,
+                axis,
+                material,
+            } => {
+                if !is_positive(major_radius) {
+                    return Err(self.invalid(format!("{}.major_radius", field), "must be positive"));
+                }
+                if !is_positive(minor_radius) {
+                    return Err(self.invalid(format!("{}.minor_radius", field), "must be positive"));
+                }
+                let material = self.lookup(field, &material, materials)?;
+                let mut torus = Torus::new(vec3(center), major_radius, minor_radius, material);
+                if let Some(axis) = axis {
+                    if !is_positive(vec3(axis).length_squared()) {
+                        return Err(self.invalid(format!("{}.axis", field), "must not be zero"));
+                    }
+                    torus = torus.with_axis(vec3(axis));
+                }
+                objects.push(HittableEnum::Torus(torus));
+            }
+            ObjectDescription::Obj { path, material } => {
+                let material = match material {
+                    Some(name) => self.lookup(field, &name, materials)?,
+                    None => Material::Lambertian(Texture::Solid(Color::new(0.8, 0.8, 0.8))),
+                };
+                objects.extend(load_obj_hittables(self.base_dir.join(path), material)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::*;
+    use crate::ray::*;
+
+    fn parse(source: &str) -> Result<Scene, SceneError> {
+        parse_scene(source, Path::new("/nonexistent/scene.toml"))
+    }
+
+    // The field an invalid scene is rejected for.
+    fn invalid_field(source: &str) -> String {
+        match parse(source) {
+            Err(SceneError::Invalid { path, field, .. }) => {
+                assert_eq!(path, Path::new("/nonexistent/scene.toml"));
+                field
+            }
+            Err(err) => panic!("expected an invalid field, got {}", err),
+            Ok(_) => panic!("expected an invalid field"),
+        }
+    }
+
+    // A scene with one object of the given type and fields, using a material called m.
+    fn object(fields: &str) -> String {
+        format!(
+            "[materials.m]\ntype = \"lambertian\"\nalbedo = [0.5, 0.5, 0.5]\n\n\
+             [[objects]]\nmaterial = \"m\"\n{}\n",
+            fields
+        )
+    }
+
+    #[test]
+    fn test_defaults() {
+        let scene = parse("").unwrap();
+        let camera = scene.camera;
+        assert_eq!(camera.image_width, 400);
+        assert_eq!(camera.samples_per_pixel, 100);
+        assert_eq!(camera.vfov, 90.0);
+        assert_eq!(camera.sampler, SamplerKind::Random);
+        assert!(matches!(camera.background, Background::Gradient { .. }));
+        assert!(scene.world.objects.is_empty());
+    }
+
+    #[test]
+    fn test_scene() {
+        let source = r#"
+            [camera]
+            image_width = 32
+            samples_per_pixel = 4
+            look_from = [0.0, 0.0, 3.0]
+            look_at = [0.0, 0.0, -1.0]
+            threads = 2
+            seed = 7
+            sampler = "halton"
+            background = { type = "solid", color = [0.1, 0.2, 0.3] }
+
+            [textures.tiles]
+            type = "checker"
+            scale = 0.5
+            even = "dark"
+            odd = [0.9, 0.9, 0.9]
+
+            [textures.dark]
+            type = "solid"
+            color = [0.1, 0.1, 0.1]
+
+            [materials.floor]
+            type = "lambertian"
+            albedo = "tiles"
+
+            [materials.lamp]
+            type = "diffuse_light"
+            emit = [4.0, 4.0, 4.0]
+
+            [[objects]]
+            type = "sphere"
+            center = [0.0, 0.0, -1.0]
+            radius = 0.5
+            material = "lamp"
+
+            [[objects]]
+            type = "plane"
+            point = [0.0, -0.5, 0.0]
+            normal = [0.0, 1.0, 0.0]
+            material = "floor"
+
+            [[objects]]
+            type = "box"
+            min = [-1.0, -0.5, -3.0]
+            max = [1.0, 0.5, -2.0]
+            material = "floor"
+            faces = { front = "lamp" }
+            rotation = { axis = [0.0, 1.0, 0.0], degrees = 30.0 }
+        "#;
+        let scene = parse(source).unwrap();
+        let camera = &scene.camera;
+        assert_eq!((camera.image_width, camera.samples_per_pixel), (32, 4));
+        assert_eq!((camera.threads, camera.seed), (2, 7));
+        assert_eq!(camera.sampler, SamplerKind::Halton);
+        // focused on look_at unless told otherwise
+        assert_eq!(camera.focus_dist, 4.0);
+        assert!(matches!(camera.background, Background::Solid(Vec3(x, _, _)) if x == 0.1));
+
+        let objects = &scene.world.objects;
+        assert_eq!(objects.len(), 3);
+        assert!(matches!(objects[0], HittableEnum::Sphere(_)));
+        assert!(matches!(objects[1], HittableEnum::Plane(_)));
+        assert!(matches!(objects[2], HittableEnum::Cuboid(_)));
+
+        // the floor's checker refers to the dark texture by name
+        let ray = Ray::new(Vec3(0.25, 1.0, 0.25), Vec3(0.0, -1.0, 0.0));
+        let mut rec = HitRecord::new();
+        assert!(objects[1].hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        let albedo = match rec.material {
+            Material::Lambertian(texture) => texture.value(rec.u, rec.v, rec.point),
+            _ => panic!("expected the floor material"),
+        };
+        assert!([0.1, 0.9].contains(&albedo.x()));
+        let ray = Ray::new(Vec3(0.0, 0.0, 0.0), Vec3(0.0, 0.0, -1.0));
+        assert!(objects[0].hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert_eq!(rec.material.emitted().x(), 4.0);
+    }
+
+    #[test]
+    fn test_playaround() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/playaround.toml");
+        let scene = load_scene(path).unwrap();
+        assert!(!scene.world.objects.is_empty());
+    }
+
+    #[test]
+    fn test_names() {
+        let unknown_material = object("type = \"sphere\"\ncenter = [0, 0, 0]\nradius = 1.0");
+        let unknown_material = unknown_material.replace("material = \"m\"", "material = \"x\"");
+        assert_eq!(invalid_field(&unknown_material), "objects[0].material");
+        let unknown_face = object(
+            "type = \"box\"\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\nfaces = { top = \"x\" }",
+        );
+        assert_eq!(invalid_field(&unknown_face), "objects[0].faces.top");
+
+        let unknown_texture = "[materials.m]\ntype = \"lambertian\"\nalbedo = \"x\"\n";
+        assert_eq!(invalid_field(unknown_texture), "materials.m.albedo");
+        let unknown_cell = "[textures.c]\ntype = \"checker\"\nscale = 1.0\n\
+                            even = \"x\"\nodd = [0.0, 0.0, 0.0]\n";
+        assert_eq!(invalid_field(unknown_cell), "textures.c.even");
+
+        // textures that refer to themselves, directly or through another one
+        let cycle = "[textures.a]\ntype = \"checker\"\nscale = 1.0\neven = \"b\"\n\
+                     odd = [0.0, 0.0, 0.0]\n\n\
+                     [textures.b]\ntype = \"uv_checker\"\ncolumns = 2.0\nrows = 2.0\n\
+                     even = [0.0, 0.0, 0.0]\nodd = \"a\"\n";
+        assert_eq!(invalid_field(cycle), "textures.a");
+        let own = "[textures.a]\ntype = \"checker\"\nscale = 1.0\neven = \"a\"\n\
+                   odd = [0.0, 0.0, 0.0]\n";
+        assert_eq!(invalid_field(own), "textures.a");
+    }
+
+    #[test]
+    fn test_invalid_objects() {
+        let cases = [
+            (
+                "type = \"sphere\"\ncenter = [0, 0, 0]\nradius = 0.0",
+                "radius",
+            ),
+            (
+                "type = \"sphere\"\ncenter = [0, 0, 0]\nradius = nan",
+                "radius",
+            ),
+            (
+                "type = \"sphere\"\ncenter = [0, 0, 0]\nradius = inf",
+                "radius",
+            ),
+            (
+                "type = \"half_sphere\"\ncenter = [0, 0, 0]\nradius = 1.0\ncut = [0, 0, 0]",
+                "cut",
+            ),
+            (
+                "type = \"quad\"\ncorner = [0, 0, 0]\nu = [1, 0, 0]\nv = [2, 0, 0]",
+                "u",
+            ),
+            (
+                "type = \"plane\"\npoint = [0, 0, 0]\nnormal = [0, 0, 0]",
+                "normal",
+            ),
+            (
+                "type = \"plane\"\npoint = [0, 0, 0]\nnormal = [0, nan, 1]",
+                "normal",
+            ),
+            (
+                "type = \"disk\"\ncenter = [0, 0, 0]\nnormal = [0, 1, 0]\nradius = -1.0",
+                "radius",
+            ),
+            (
+                "type = \"disk\"\ncenter = [0, 0, 0]\nnormal = [0, 0, 0]\nradius = 1.0",
+                "normal",
+            ),
+            ("type = \"box\"\nmin = [0, 0, 0]\nmax = [1, 0, 1]", "max"),
+            ("type = \"box\"\nmin = [0, 0, 0]\nmax = [1, nan, 1]", "max"),
+            (
+                "type = \"box\"\nmin = [0, 0, 0]\nmax = [1, 1, 1]\n\
+                 rotation = { axis = [0, 0, 0], degrees = 10.0 }",
+                "rotation.axis",
+            ),
+            (
+                "type = \"cylinder\"\nbase = [0, 0, 0]\ntop = [0, 0, 0]\nradius = 1.0",
+                "top",
+            ),
+            (
+                "type = \"cone\"\nbase = [0, 0, 0]\ntop = [0, 1, 0]\nradius = 1.0\n\
+                 top_radius = -0.5",
+                "top_radius",
+            ),
+            (
+                "type = \"capsule\"\nbase = [0, 0, 0]\ntop = [0, 1, 0]\nradius = nan",
+                "radius",
+            ),
+            (
+                "type = \"torus\"\ncenter = [0, 0, 0]\nmajor_radius = 1.0\n\
+                 minor_radius = 0.0",
+                "minor_radius",
+            ),
+            (
+                "type = \"torus\"\ncenter = [0, 0, 0]\nmajor_radius = 1.0\n\
+                 minor_radius = 0.2\naxis = [0, 0, 0]",
+                "axis",
+            ),
+        ];
+        for (fields, field) in cases.iter() {
+            assert_eq!(
+                invalid_field(&object(fields)),
+                format!("objects[0].{}", field),
+                "{}",
+                fields
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_materials_and_textures() {
+        let cases = [
+            (
+                "[materials.m]\ntype = \"metal\"\nalbedo = [1, 1, 1]\nfuzz = -0.1",
+                "materials.m.fuzz",
+            ),
+            (
+                "[materials.m]\ntype = \"dielectric\"\nrefraction_index = nan",
+                "materials.m.refraction_index",
+            ),
+            (
+                "[textures.t]\ntype = \"checker\"\nscale = 0.0\neven = [0, 0, 0]\n\
+                 odd = [1, 1, 1]",
+                "textures.t.scale",
+            ),
+            (
+                "[textures.t]\ntype = \"uv_checker\"\ncolumns = 2.0\nrows = -inf\n\
+                 even = [0, 0, 0]\nodd = [1, 1, 1]",
+                "textures.t.rows",
+            ),
+            (
+                "[textures.t]\ntype = \"marble\"\nscale = nan",
+                "textures.t.scale",
+            ),
+        ];
+        for (source, field) in cases.iter() {
+            assert_eq!(invalid_field(source), *field, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_invalid_camera() {
+        let cases = [
+            ("aspect_ratio = nan", "camera.aspect_ratio"),
+            ("image_width = 0", "camera.image_width"),
+            ("samples_per_pixel = 0", "camera.samples_per_pixel"),
+            ("min_samples_per_pixel = -1", "camera.min_samples_per_pixel"),
+            ("noise_threshold = -0.01", "camera.noise_threshold"),
+            ("threads = 0", "camera.threads"),
+            ("vfov = 0.0", "camera.vfov"),
+            ("vfov = 180.0", "camera.vfov"),
+            ("vfov = nan", "camera.vfov"),
+            ("defocus_angle = -1.0", "camera.defocus_angle"),
+            ("focus_dist = 0.0", "camera.focus_dist"),
+            ("look_from = [0.0, inf, 0.0]", "camera.look_from"),
+            ("look_at = [nan, 0.0, 0.0]", "camera.look_at"),
+            ("look_at = [0.0, 0.0, 0.0]", "camera.look_at"),
+            ("vup = [0.0, 0.0, 0.0]", "camera.vup"),
+            ("vup = [0.0, 0.0, 2.0]", "camera.vup"),
+        ];
+        for (line, field) in cases.iter() {
+            assert_eq!(
+                invalid_field(&format!("[camera]\n{}\n", line)),
+                *field,
+                "{}",
+                line
+            );
+        }
+        // a tilted up vector is fine
+        assert!(parse("[camera]\nvup = [1.0, 1.0, 0.0]\n").is_ok());
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(matches!(
+            parse("[camera]\nimage_width = \"wide\"\n"),
+            Err(SceneError::Parse { .. })
+        ));
+        assert!(matches!(
+            parse("[camera]\nzoom = 2.0\n"),
+            Err(SceneError::Parse { .. })
+        ));
+        assert!(matches!(
+            parse("[[objects]]\ntype = \"teapot\"\n"),
+            Err(SceneError::Parse { .. })
+        ));
+        assert!(matches!(
+            load_scene("/nonexistent/scene.toml"),
+            Err(SceneError::Io { .. })
+        ));
+
+        // files the scene refers to are found next to it
+        match parse("[[objects]]\ntype = \"obj\"\npath = \"models/teapot.obj\"\n") {
+            Err(SceneError::Obj(ObjError::Io { path, .. })) => {
+                assert_eq!(path, Path::new("/nonexistent/models/teapot.obj"))
+            }
+            Err(err) => panic!("expected an obj error, got {}", err),
+            Ok(_) => panic!("expected an obj error"),
+        }
+        match parse("[textures.t]\ntype = \"image\"\npath = \"wood.png\"\n") {
+            Err(SceneError::Image(ImageError::Io { path, .. })) => {
+                assert_eq!(path, Path::new("/nonexistent/wood.png"))
+            }
+            Err(err) => panic!("expected an image error, got {}", err),
+            Ok(_) => panic!("expected an image error"),
+        }
+    }
+}
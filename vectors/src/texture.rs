@@ -0,0 +1,380 @@
+use crate::framebuffer::*;
+use crate::image::*;
+use crate::perlin::*;
+use crate::vec3::*;
+use std::path::Path;
+use std::sync::Arc;
+
+// A color that varies over a surface, looked up by the texture coordinates u, v
+// and the world space point p of a hit.
+#[derive(Debug, Clone)]
+pub enum Texture {
+    Solid(Color),
+    // cubes with edges of length scale, alternating between even and odd in 3D space
+    Checker {
+        scale: f64,
+        even: Arc<Texture>,
+        odd: Arc<Texture>,
+    },
+    // columns by rows squares over the 0..1 texture coordinate range
+    UvChecker {
+        columns: f64,
+        rows: f64,
+        even: Arc<Texture>,
+        odd: Arc<Texture>,
+    },
+    Image(Arc<ImageTexture>),
+    // a pattern of Perlin noise in 3D space, blending from low to high
+    Noise {
+        perlin: Arc<Perlin>,
+        pattern: NoisePattern,
+        scale: f64,
+        low: Color,
+        high: Color,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoisePattern {
+    // several octaves of noise, like clouds or stains
+    Turbulence,
+    // veins along the z axis, disturbed by turbulence
+    Marble,
+    // rings around the y axis, disturbed by noise
+    Wood,
+}
+
+impl NoisePattern {
+    // How far from low to high the pattern is at p, in 0..1.
+    fn value(&self, perlin: &Perlin, scale: f64, p: Point3) -> f64 {
+        const OCTAVES: u32 = 7;
+        let t = match self {
+            NoisePattern::Turbulence => perlin.turbulence(p * scale, OCTAVES),
+            NoisePattern::Marble => {
+                0.5 * (1.0 + (scale * p.z() + 10.0 * perlin.turbulence(p, OCTAVES)).sin())
+            }
+            NoisePattern::Wood => {
+                let rings = scale * p.x().hypot(p.z()) + 2.0 * perlin.noise(p * scale * 0.5);
+                // sharpen the fraction so every ring has a thin dark edge
+                (rings - rings.floor()).powi(3)
+            }
+        };
+        t.clamp(0.0, 1.0)
+    }
+}
+
+impl Texture {
+    pub fn checker<T: Into<Texture>>(scale: f64, even: T, odd: T) -> Self {
+        Texture::Checker {
+            scale,
+            even: Arc::new(even.into()),
+            odd: Arc::new(odd.into()),
+        }
+    }
+
+    pub fn uv_checker<T: Into<Texture>>(columns: f64, rows: f64, even: T, odd: T) -> Self {
+        Texture::UvChecker {
+            columns,
+            rows,
+            even: Arc::new(even.into()),
+            odd: Arc::new(odd.into()),
+        }
+    }
+
+    pub fn noise(pattern: NoisePattern, seed: u64, scale: f64, low: Color, high: Color) -> Self {
+        Texture::Noise {
+            perlin: Arc::new(Perlin::new(seed)),
+            pattern,
+            scale,
+            low,
+            high,
+        }
+    }
+
+    pub fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Checker { scale, even, odd } => {
+                // only the parity of each cell index matters, and summing the indices
+                // themselves could overflow far from the origin
+                let cell = |x: f64| ((x / scale).floor() as i64).rem_euclid(2);
+                if (cell(p.x()) + cell(p.y()) + cell(p.z())) % 2 == 0 {
+                    even.value(u, v, p)
+                } else {
+                    odd.value(u, v, p)
+                }
+            }
+            Texture::UvChecker {
+                columns,
+                rows,
+                even,
+                odd,
+            } => {
+                let column = ((u * columns).floor() as i64).rem_euclid(2);
+                let row = ((v * rows).floor() as i64).rem_euclid(2);
+                if (column + row) % 2 == 0 {
+                    even.value(u, v, p)
+                } else {
+                    odd.value(u, v, p)
+                }
+            }
+            Texture::Image(image) => image.value(u, v),
+            Texture::Noise {
+                perlin,
+                pattern,
+                scale,
+                low,
+                high,
+            } => {
+                let t = pattern.value(perlin, *scale, p);
+                *low * (1.0 - t) + *high * t
+            }
+        }
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Self {
+        Texture::Solid(color)
+    }
+}
+
+// How texture coordinates outside 0..1 are mapped onto the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wrap {
+    // the image tiles the plane
+    Repeat,
+    // the edge pixels extend outwards
+    Clamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    Nearest,
+    // blends the four pixels around the lookup point
+    Bilinear,
+}
+
+// Linear colors, row by row from the top, mapped onto the texture coordinates with
+// u = 0 at the left edge and v = 0 at the bottom edge.
+#[derive(Debug, Clone)]
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    wrap: Wrap,
+    filter: Filter,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "{}x{} image texture needs {} pixels",
+            width,
+            height,
+            width * height
+        );
+        Self {
+            width,
+            height,
+            pixels,
+            wrap: Wrap::Repeat,
+            filter: Filter::Bilinear,
+        }
+    }
+
+    pub fn from_framebuffer(framebuffer: &Framebuffer) -> Self {
+        Self::new(
+            framebuffer.width(),
+            framebuffer.height(),
+            framebuffer.pixels().to_vec(),
+        )
+    }
+
+    // Converts the sRGB encoded colors of an image file to linear ones, unless srgb is
+    // false because the image holds linear data already.
+    pub fn from_decoded(image: DecodedImage, srgb: bool) -> Self {
+        let pixels = if srgb {
+            image
+                .pixels
+                .iter()
+                .map(|c| {
+                    Vec3(
+                        srgb_to_linear(c.x()),
+                        srgb_to_linear(c.y()),
+                        srgb_to_linear(c.z()),
+                    )
+                })
+                .collect()
+        } else {
+            image.pixels
+        };
+        Self::new(image.width, image.height, pixels)
+    }
+
+    // Loads an sRGB encoded PNG or PPM file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        Ok(Self::from_decoded(load_image(path)?, true))
+    }
+
+    pub fn with_wrap(mut self, wrap: Wrap) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Color {
+        let (x, y) = match self.wrap {
+            Wrap::Repeat => (
+                x.rem_euclid(self.width as i64),
+                y.rem_euclid(self.height as i64),
+            ),
+            Wrap::Clamp => (
+                x.clamp(0, self.width as i64 - 1),
+                y.clamp(0, self.height as i64 - 1),
+            ),
+        };
+        self.pixels[y as usize * self.width + x as usize]
+    }
+
+    pub fn value(&self, u: f64, v: f64) -> Color {
+        if self.pixels.is_empty() {
+            // a missing image shows up as cyan
+            return Vec3(0.0, 1.0, 1.0);
+        }
+        // continuous pixel coordinates, with pixel centers at whole numbers
+        let x = u * self.width as f64 - 0.5;
+        let y = (1.0 - v) * self.height as f64 - 0.5;
+        match self.filter {
+            Filter::Nearest => self.pixel(x.round() as i64, y.round() as i64),
+            Filter::Bilinear => {
+                let (x0, y0) = (x.floor(), y.floor());
+                let (fx, fy) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.pixel(x0, y0) * (1.0 - fx) + self.pixel(x0 + 1, y0) * fx;
+                let bottom = self.pixel(x0, y0 + 1) * (1.0 - fx) + self.pixel(x0 + 1, y0 + 1) * fx;
+                top * (1.0 - fy) + bottom * fy
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: f64) -> Color {
+        Vec3(value, value, value)
+    }
+
+    fn xyz(v: Vec3) -> (f64, f64, f64) {
+        (v.x(), v.y(), v.z())
+    }
+
+    #[test]
+    fn test_checker() {
+        let checker = Texture::checker(0.5, gray(1.0), gray(0.0));
+        let at = |x: f64, y: f64, z: f64| checker.value(0.0, 0.0, Vec3(x, y, z)).x();
+        assert_eq!(at(0.1, 0.1, 0.1), 1.0);
+        assert_eq!(at(0.6, 0.1, 0.1), 0.0);
+        assert_eq!(at(0.6, 0.6, 0.1), 1.0);
+        assert_eq!(at(0.6, 0.6, 0.6), 0.0);
+        // the cells keep alternating across zero rather than mirroring
+        assert_eq!(at(-0.1, 0.1, 0.1), 0.0);
+        assert_eq!(at(-0.6, 0.1, 0.1), 1.0);
+        assert_eq!(at(-0.1, -0.1, -0.1), 0.0);
+        assert_eq!(at(-0.1, -0.1, 0.1), 1.0);
+        // far from the origin, e.g. on an infinite plane, without overflowing
+        assert_eq!(at(1e12 + 0.6, 0.1, 0.1), 0.0);
+        assert_eq!(at(-1e12 - 0.6, -1e12 - 0.6, 0.1), 1.0);
+        // the cell indices saturate here, so either color will do
+        for &x in &[1e300, -1e300, f64::INFINITY, f64::NAN] {
+            assert!([0.0, 1.0].contains(&at(x, x, -x)));
+        }
+    }
+
+    #[test]
+    fn test_uv_checker() {
+        let checker = Texture::uv_checker(4.0, 2.0, gray(1.0), gray(0.0));
+        let at = |u: f64, v: f64| checker.value(u, v, Vec3(0.0, 0.0, 0.0)).x();
+        assert_eq!(at(0.1, 0.1), 1.0);
+        assert_eq!(at(0.3, 0.1), 0.0);
+        assert_eq!(at(0.6, 0.1), 1.0);
+        assert_eq!(at(0.9, 0.1), 0.0);
+        assert_eq!(at(0.1, 0.6), 0.0);
+        assert_eq!(at(0.3, 0.6), 1.0);
+        assert_eq!(at(1e12 + 0.3, 0.1), 0.0);
+        for &u in &[1e300, -1e300, f64::INFINITY, f64::NAN] {
+            assert!([0.0, 1.0].contains(&at(u, -u)));
+        }
+    }
+
+    #[test]
+    fn test_srgb_to_linear() {
+        assert_eq!(srgb_to_linear(0.0), 0.0);
+        assert_eq!(srgb_to_linear(1.0), 1.0);
+        assert!((srgb_to_linear(0.5) - 0.214041).abs() < 1e-6);
+        // the linear segment near black
+        assert_eq!(srgb_to_linear(0.04), 0.04 / 12.92);
+        // and the two pieces meet
+        let below = srgb_to_linear(0.04045);
+        let above = srgb_to_linear(0.04045 + 1e-9);
+        assert!((above - below).abs() < 1e-6);
+
+        let decoded = DecodedImage {
+            width: 1,
+            height: 1,
+            pixels: vec![Vec3(0.0, 0.5, 1.0)],
+        };
+        let linear = ImageTexture::from_decoded(decoded, true).value(0.5, 0.5);
+        assert_eq!(xyz(linear), (0.0, srgb_to_linear(0.5), 1.0));
+        let decoded = DecodedImage {
+            width: 1,
+            height: 1,
+            pixels: vec![Vec3(0.0, 0.5, 1.0)],
+        };
+        let raw = ImageTexture::from_decoded(decoded, false).value(0.5, 0.5);
+        assert_eq!(xyz(raw), (0.0, 0.5, 1.0));
+    }
+
+    #[test]
+    fn test_image_uv() {
+        // 2x2, top row red and green, bottom row blue and white
+        let pixels = vec![
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(0.0, 1.0, 0.0),
+            Vec3(0.0, 0.0, 1.0),
+            Vec3(1.0, 1.0, 1.0),
+        ];
+        let image = ImageTexture::new(2, 2, pixels).with_filter(Filter::Nearest);
+        // v = 0 is the bottom edge
+        assert_eq!(xyz(image.value(0.25, 0.75)), (1.0, 0.0, 0.0));
+        assert_eq!(xyz(image.value(0.75, 0.75)), (0.0, 1.0, 0.0));
+        assert_eq!(xyz(image.value(0.25, 0.25)), (0.0, 0.0, 1.0));
+        assert_eq!(xyz(image.value(0.75, 0.25)), (1.0, 1.0, 1.0));
+        // repeating wraps around, clamping stays at the edge
+        assert_eq!(xyz(image.value(1.25, -0.25)), (1.0, 0.0, 0.0));
+        let clamped = image.clone().with_wrap(Wrap::Clamp);
+        assert_eq!(xyz(clamped.value(1.25, -0.25)), (1.0, 1.0, 1.0));
+
+        // halfway between the pixel centers blends them
+        let bilinear = image.with_filter(Filter::Bilinear).with_wrap(Wrap::Clamp);
+        assert_eq!(xyz(bilinear.value(0.5, 0.75)), (0.5, 0.5, 0.0));
+        assert_eq!(xyz(bilinear.value(0.25, 0.5)), (0.5, 0.0, 0.5));
+        assert_eq!(xyz(bilinear.value(0.0, 1.0)), (1.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,258 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::math::*;
+use crate::planar::*;
+use crate::ray::*;
+use crate::vec3::*;
+use std::f64::consts::PI;
+
+// A ring of radius major_radius around center, lying in the plane perpendicular to axis,
+// with a circular cross section of radius minor_radius. u is the angle around the axis
+// and v the angle around the tube, both 0..1.
+pub struct Torus {
+    center: Point3,
+    axis: Vec3,
+    major_radius: f64,
+    minor_radius: f64,
+    // with axis, the torus' frame
+    tangent: Vec3,
+    bitangent: Vec3,
+    material: Material,
+}
+
+impl Torus {
+    // A torus lying flat, around the y axis.
+    pub fn new(center: Point3, major_radius: f64, minor_radius: f64, material: Material) -> Self {
+        Self::with_frame(
+            center,
+            Vec3(0.0, 1.0, 0.0),
+            major_radius,
+            minor_radius,
+            material,
+        )
+    }
+
+    pub fn with_axis(self, axis: Vec3) -> Self {
+        Self::with_frame(
+            self.center,
+            axis.unit_vector(),
+            self.major_radius,
+            self.minor_radius,
+            self.material,
+        )
+    }
+
+    fn with_frame(
+        center: Point3,
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material: Material,
+    ) -> Self {
+        let (tangent, bitangent) = plane_basis(axis);
+        Self {
+            center,
+            axis,
+            major_radius,
+            minor_radius,
+            tangent,
+            bitangent,
+            material,
+        }
+    }
+
+    // v in the torus' frame, with the axis as y.
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3(v.dot(self.tangent), v.dot(self.axis), v.dot(self.bitangent))
+    }
+}
+
+impl Hittable for Torus {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        let (big, small) = (self.major_radius, self.minor_radius);
+
+        // The quartic is solved for a unit length direction and an origin moved along the
+        // ray to the point closest to the center. Far away origins would otherwise give
+        // coefficients so large that the roots drown in rounding errors.
+        let length = ray.dir.length();
+        let d = self.to_local(ray.dir) / length;
+        let far_origin = self.to_local(ray.origin - self.center);
+        let shift = -far_origin.dot(d);
+        let o = far_origin + d * shift;
+
+        // A ray that misses the bounding sphere can't hit the torus.
+        if o.length_squared() > (big + small) * (big + small) {
+            return false;
+        }
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) for p = o + s d
+        let n = o.dot(d);
+        let k = o.length_squared() + big * big - small * small;
+        let four_r2 = 4.0 * big * big;
+        let roots = solve_quartic(
+            1.0,
+            4.0 * n,
+            4.0 * n * n + 2.0 * k - four_r2 * (d.x() * d.x() + d.z() * d.z()),
+            4.0 * n * k - 2.0 * four_r2 * (o.x() * d.x() + o.z() * d.z()),
+            k * k - four_r2 * (o.x() * o.x() + o.z() * o.z()),
+        );
+        let t = match roots
+            .as_slice()
+            .iter()
+            .map(|s| (s + shift) / length)
+            .find(|&t| ray_t.surrounds(t))
+        {
+            Some(t) => t,
+            None => return false,
+        };
+
+        rec.t = t;
+        rec.point = ray.at(t);
+        let p = self.to_local(rec.point - self.center);
+        let ring = p.x().hypot(p.z());
+        // the point on the ring at the middle of the tube closest to p
+        let core = if ring > 0.0 {
+            Vec3(p.x(), 0.0, p.z()) * (big / ring)
+        } else {
+            Vec3(big, 0.0, 0.0)
+        };
+        let local_normal = (p - core).unit_vector();
+        let outward_normal = self.tangent * local_normal.x()
+            + self.axis * local_normal.y()
+            + self.bitangent * local_normal.z();
+        rec.set_face_normal(ray, outward_normal);
+        (rec.u, rec.v) = (
+            (p.z().atan2(p.x()) + PI) / (2.0 * PI),
+            (p.y().atan2(ring - big) + PI) / (2.0 * PI),
+        );
+        rec.material = &self.material;
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // the box around the ring, grown by the tube's radius
+        let extent = |n: f64| self.major_radius * (1.0 - n * n).max(0.0).sqrt() + self.minor_radius;
+        let e = Vec3(
+            extent(self.axis.x()),
+            extent(self.axis.y()),
+            extent(self.axis.z()),
+        );
+        Aabb::from_points(self.center - e, self.center + e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::*;
+
+    // Marches rays through the torus in small steps and checks that the first hit is
+    // where the signed distance first changes sign, and that the normal points out.
+    #[test]
+    fn test_hit_against_ray_marching() {
+        let (center, axis) = (Vec3(0.3, -0.2, 0.1), Vec3(0.4, 1.0, -0.3).unit_vector());
+        let (big, small) = (1.0, 0.3);
+        let torus = Torus::new(center, big, small, Material::None).with_axis(axis);
+        let distance = |p: Point3| {
+            let p = torus.to_local(p - center);
+            (p.x().hypot(p.z()) - big).hypot(p.y()) - small
+        };
+
+        let mut rng = seeded_rng(5);
+        let step = 1e-3;
+        let mut hits = 0;
+        for i in 0..2000 {
+            // some rays start inside the tube, some far away
+            let origin = match i % 4 {
+                0 => center + random_unit_vector(&mut rng) * 40.0,
+                1 => {
+                    let p = random_unit_vector(&mut rng);
+                    center + (torus.tangent * p.x() + torus.bitangent * p.z()).unit_vector()
+                }
+                _ => center + random_with_range(&mut rng, -2.0, 2.0),
+            };
+            let target = center + random_with_range(&mut rng, -1.3, 1.3);
+            let ray = Ray::new(origin, (target - origin) * 0.5);
+            let mut rec = HitRecord::new();
+            let hit = torus.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec);
+
+            // March in steps of step along the unit direction, only through the bounding
+            // sphere since nothing can be hit outside it.
+            let length = ray.dir.length();
+            let closest = (center - origin).dot(ray.dir) / length;
+            let mut s = (closest - big - small).max(0.001 * length);
+            let inside = distance(ray.at(s / length)) < 0.0;
+            let mut marched = None;
+            while s < closest + big + small {
+                s += step;
+                if (distance(ray.at(s / length)) < 0.0) != inside {
+                    marched = Some(s / length);
+                    break;
+                }
+            }
+
+            match marched {
+                Some(t) => {
+                    assert!(hit, "missed the torus at {}", t);
+                    hits += 1;
+                    assert!(
+                        (rec.t - t).abs() <= step / length,
+                        "hit at {}, marched to {}",
+                        rec.t,
+                        t
+                    );
+                    assert!(distance(rec.point).abs() < 1e-9);
+                    let outward = if rec.front_face {
+                        rec.normal
+                    } else {
+                        -rec.normal
+                    };
+                    assert!(distance(rec.point + outward * 1e-4) > 0.0);
+                    assert!(distance(rec.point - outward * 1e-4) < 0.0);
+                    assert!((0.0..=1.0).contains(&rec.u) && (0.0..=1.0).contains(&rec.v));
+                    let bbox = torus.bounding_box();
+                    assert!(
+                        bbox.x.contains(rec.point.x())
+                            && bbox.y.contains(rec.point.y())
+                            && bbox.z.contains(rec.point.z())
+                    );
+                }
+                None => assert!(!hit, "hit at {} but marching found nothing", rec.t),
+            }
+        }
+        assert!(hits > 500);
+    }
+
+    #[test]
+    fn test_uv() {
+        let torus = Torus::new(Vec3(0.0, 0.0, 0.0), 1.0, 0.25, Material::None);
+        let uv_at = |origin: Point3, dir: Vec3| {
+            let mut rec = HitRecord::new();
+            assert!(torus.hit(
+                &Ray::new(origin, dir),
+                Interval::new(0.001, f64::INFINITY),
+                &mut rec
+            ));
+            (rec.u, rec.v)
+        };
+        let near = |(u, v): (f64, f64), expected: (f64, f64)| {
+            assert!(
+                (u - expected.0).abs() < 1e-9 && (v - expected.1).abs() < 1e-9,
+                "({}, {}), expected {:?}",
+                u,
+                v,
+                expected
+            );
+        };
+        // u goes around the axis from -x, v around the tube from its inside
+        let down = Vec3(0.0, -1.0, 0.0);
+        near(uv_at(Vec3(1.0, 5.0, 0.0), down), (0.5, 0.75));
+        near(uv_at(Vec3(0.0, 5.0, -1.0), down), (0.75, 0.75));
+        near(uv_at(Vec3(0.0, 5.0, 1.0), down), (0.25, 0.75));
+        near(uv_at(Vec3(0.0, -5.0, -1.0), -down), (0.75, 0.25));
+        // the outer equator
+        near(uv_at(Vec3(5.0, 0.0, 0.0), Vec3(-1.0, 0.0, 0.0)), (0.5, 0.5));
+    }
+}
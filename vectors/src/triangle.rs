@@ -0,0 +1,269 @@
+use crate::aabb::*;
+use crate::hittable::*;
+use crate::interval::*;
+use crate::material::*;
+use crate::ray::*;
+use crate::vec3::*;
+
+pub type Uv = (f64, f64);
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[Uv; 3]>,
+    material: Material,
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3, material: Material) -> Self {
+        Self {
+            vertices: [a, b, c],
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    // Per-vertex normals, interpolated across the face for smooth shading.
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    // Per-vertex texture coordinates, interpolated across the face.
+    pub fn with_uvs(mut self, uvs: [Uv; 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit<'a>(&'a self, ray: &Ray, ray_t: Interval, rec: &mut HitRecord<'a>) -> bool {
+        match intersect(ray, ray_t, &self.vertices) {
+            Some((t, b1, b2)) => {
+                let surface = Surface {
+                    vertices: &self.vertices,
+                    normals: self.normals.as_ref(),
+                    uvs: self.uvs.as_ref(),
+                };
+                surface.fill_record(ray, t, b1, b2, &self.material, rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(&self.vertices)
+    }
+}
+
+// Möller–Trumbore ray/triangle intersection.
+// Returns the ray parameter and the barycentric coordinates of the hit relative to v1 and v2.
+pub(crate) fn intersect(ray: &Ray, ray_t: Interval, v: &[Point3; 3]) -> Option<(f64, f64, f64)> {
+    const EPSILON: f64 = 1e-12;
+
+    let edge1 = v[1] - v[0];
+    let edge2 = v[2] - v[0];
+    let pvec = ray.dir.cross(edge2);
+    let det = edge1.dot(pvec);
+
+    // The ray is parallel to the triangle's plane.
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = ray.origin - v[0];
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(edge1);
+    let b2 = ray.dir.dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(qvec) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+pub(crate) fn triangle_bounding_box(v: &[Point3; 3]) -> Aabb {
+    Aabb::enclosing(
+        &Aabb::from_points(v[0], v[1]),
+        &Aabb::from_points(v[0], v[2]),
+    )
+}
+
+// The per-vertex attributes of one triangle, borrowed from wherever they are stored.
+pub(crate) struct Surface<'a> {
+    pub vertices: &'a [Point3; 3],
+    pub normals: Option<&'a [Vec3; 3]>,
+    pub uvs: Option<&'a [Uv; 3]>,
+}
+
+impl Surface<'_> {
+    pub fn fill_record<'m>(
+        &self,
+        ray: &Ray,
+        t: f64,
+        b1: f64,
+        b2: f64,
+        material: &'m Material,
+        rec: &mut HitRecord<'m>,
+    ) {
+        let b0 = 1.0 - b1 - b2;
+        let v = self.vertices;
+
+        rec.t = t;
+        rec.point = ray.at(t);
+        let geometric_normal = (v[1] - v[0]).cross(v[2] - v[0]).unit_vector();
+        rec.set_face_normal(ray, geometric_normal);
+
+        if let Some(n) = self.normals {
+            // Keep the shading normal on the same side as the geometric one,
+            // so front_face stays consistent with the winding order.
+            let mut shading_normal = (n[0] * b0 + n[1] * b1 + n[2] * b2).unit_vector();
+            if shading_normal.dot(geometric_normal) < 0.0 {
+                shading_normal = -shading_normal;
+            }
+            rec.normal = if rec.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+
+        (rec.u, rec.v) = match self.uvs {
+            Some(uv) => (
+                uv[0].0 * b0 + uv[1].0 * b1 + uv[2].0 * b2,
+                uv[0].1 * b0 + uv[1].1 * b1 + uv[2].1 * b2,
+            ),
+            None => (b1, b2),
+        };
+        rec.material = material;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xyz(v: Vec3) -> (f64, f64, f64) {
+        (v.x(), v.y(), v.z())
+    }
+
+    // The right triangle with its corner at (0, 0, -1), facing +z.
+    fn right_triangle() -> Triangle {
+        Triangle::new(
+            Vec3(0.0, 0.0, -1.0),
+            Vec3(1.0, 0.0, -1.0),
+            Vec3(0.0, 1.0, -1.0),
+            Material::None,
+        )
+    }
+
+    // Fires a ray straight down -z at (x, y), from in front of the triangle.
+    fn hit_at<'a>(triangle: &'a Triangle, x: f64, y: f64) -> Option<HitRecord<'a>> {
+        let ray = Ray::new(Vec3(x, y, 0.0), Vec3(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new();
+        if triangle.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec) {
+            Some(rec)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_hit_and_edges() {
+        let triangle = right_triangle();
+        let rec = hit_at(&triangle, 0.25, 0.5).unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(xyz(rec.point), (0.25, 0.5, -1.0));
+        assert!(rec.front_face);
+        assert_eq!(xyz(rec.normal), (0.0, 0.0, 1.0));
+        // without texture coordinates, u and v are the barycentric coordinates
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+
+        // just inside and just outside each edge
+        let e = 1e-6;
+        assert!(hit_at(&triangle, 0.5, e).is_some());
+        assert!(hit_at(&triangle, 0.5, -e).is_none());
+        assert!(hit_at(&triangle, e, 0.5).is_some());
+        assert!(hit_at(&triangle, -e, 0.5).is_none());
+        assert!(hit_at(&triangle, 0.5 - e, 0.5 - e).is_some());
+        assert!(hit_at(&triangle, 0.5 + e, 0.5 + e).is_none());
+
+        // a ray in the triangle's plane never hits it
+        let ray = Ray::new(Vec3(-1.0, 0.2, -1.0), Vec3(1.0, 0.0, 0.0));
+        let mut rec = HitRecord::new();
+        assert!(!triangle.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        // nor does one that would hit it outside ray_t
+        let ray = Ray::new(Vec3(0.2, 0.2, 0.0), Vec3(0.0, 0.0, -1.0));
+        assert!(!triangle.hit(&ray, Interval::new(0.001, 0.5), &mut rec));
+    }
+
+    #[test]
+    fn test_back_face() {
+        let triangle = right_triangle();
+        let ray = Ray::new(Vec3(0.2, 0.2, -3.0), Vec3(0.0, 0.0, 1.0));
+        let mut rec = HitRecord::new();
+        assert!(triangle.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert_eq!(rec.t, 2.0);
+        assert!(!rec.front_face);
+        assert_eq!(xyz(rec.normal), (0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn test_vertex_uvs() {
+        let triangle = right_triangle().with_uvs([(0.1, 0.2), (0.9, 0.2), (0.5, 1.0)]);
+        assert_eq!(
+            hit_at(&triangle, 0.0, 0.0).map(|rec| (rec.u, rec.v)),
+            Some((0.1, 0.2))
+        );
+        assert_eq!(
+            hit_at(&triangle, 1.0, 0.0).map(|rec| (rec.u, rec.v)),
+            Some((0.9, 0.2))
+        );
+        assert_eq!(
+            hit_at(&triangle, 0.0, 1.0).map(|rec| (rec.u, rec.v)),
+            Some((0.5, 1.0))
+        );
+        let rec = hit_at(&triangle, 0.5, 0.0).unwrap();
+        assert!((rec.u - 0.5).abs() < 1e-12 && (rec.v - 0.2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_shading_normals() {
+        // vertex normals on the back side of the face, tilted towards +y
+        let normal = Vec3(0.0, 0.6, -0.8);
+        let triangle = right_triangle().with_normals([normal, normal, normal]);
+
+        // flipped onto the geometric normal's side, which faces the ray
+        let rec = hit_at(&triangle, 0.2, 0.2).unwrap();
+        assert!(rec.front_face);
+        assert!((rec.normal - Vec3(0.0, -0.6, 0.8)).length() < 1e-12);
+
+        // and seen from behind, towards that side
+        let ray = Ray::new(Vec3(0.2, 0.2, -3.0), Vec3(0.0, 0.0, 1.0));
+        let mut rec = HitRecord::new();
+        assert!(triangle.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rec));
+        assert!(!rec.front_face);
+        assert!((rec.normal - Vec3(0.0, 0.6, -0.8)).length() < 1e-12);
+
+        // normals that differ per vertex are interpolated
+        let triangle = right_triangle().with_normals([
+            Vec3(0.0, 0.0, 1.0),
+            Vec3(1.0, 0.0, 0.0),
+            Vec3(0.0, 0.0, 1.0),
+        ]);
+        let rec = hit_at(&triangle, 0.5, 0.0).unwrap();
+        let half = 0.5f64.sqrt();
+        assert!((rec.normal - Vec3(half, 0.0, half)).length() < 1e-12);
+    }
+}
@@ -1,11 +1,107 @@
-use rand::prelude::*;
+use rand_pcg::rand_core::RngCore;
+use rand_pcg::Pcg64Mcg;
 
-pub fn random_double() -> f64 {
-    let mut rng = rand::rng();
-    rng.random()
+// The random number generator threaded through the renderer. PCG64 (the MCG variant) has
+// a specified output for every state, and the conversions below are our own, so a seed
+// gives the same image on every platform and with every version of the rand crates.
+// Each pixel gets its own stream (see pixel_rng).
+pub type RenderRng = Pcg64Mcg;
+
+// A generator whose state is derived only from seed.
+pub fn seeded_rng(seed: u64) -> RenderRng {
+    let high = mix(seed);
+    RenderRng::new(((high as u128) << 64) | mix(high) as u128)
+}
+
+// A uniform value in [0, 1), from the top 53 bits of the next output.
+pub fn random_double(rng: &mut RenderRng) -> f64 {
+    (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub fn random_double_with_range(rng: &mut RenderRng, min: f64, max: f64) -> f64 {
+    min + (max - min) * random_double(rng)
+}
+
+// A uniform index in 0..n. Multiplying instead of taking the remainder, and rejecting
+// the few outputs that would favor some indices, keeps it free of bias (Lemire's method).
+pub fn random_index(rng: &mut RenderRng, n: usize) -> usize {
+    assert!(n > 0, "random_index needs a non-empty range");
+    let n = n as u64;
+    let threshold = n.wrapping_neg() % n;
+    loop {
+        let product = rng.next_u64() as u128 * n as u128;
+        if product as u64 >= threshold {
+            return (product >> 64) as usize;
+        }
+    }
+}
+
+// Puts items in a random order, every order equally likely (Fisher-Yates).
+pub fn shuffle<T>(rng: &mut RenderRng, items: &mut [T]) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, random_index(rng, i + 1));
+    }
 }
 
-pub fn random_double_with_range(min: f64, max: f64) -> f64 {
-    let mut rng = rand::rng();
-    rng.random_range(min..max)
+// SplitMix64 finalizer, scrambles the bits of x so nearby inputs give unrelated outputs.
+pub(crate) fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+// A generator for the pixel at column i and row j, derived only from the seed and
+// the pixel position so the result doesn't depend on which thread renders it.
+pub fn pixel_rng(seed: u64, i: i32, j: i32) -> RenderRng {
+    seeded_rng(pixel_hash(seed, i, j))
+}
+
+// A well mixed 64-bit value for the pixel at column i and row j.
+pub fn pixel_hash(seed: u64, i: i32, j: i32) -> u64 {
+    let position = ((j as u32 as u64) << 32) | i as u32 as u64;
+    mix(mix(seed) ^ position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_index() {
+        let mut rng = seeded_rng(1);
+        let mut counts = [0; 3];
+        for _ in 0..30000 {
+            counts[random_index(&mut rng, 3)] += 1;
+        }
+        for &count in counts.iter() {
+            assert!((9500..10500).contains(&count), "{:?}", counts);
+        }
+        assert_eq!(random_index(&mut rng, 1), 0);
+        // the largest range, where almost every output has to be rejected for some n
+        assert!(random_index(&mut rng, usize::MAX) < usize::MAX);
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let mut rng = seeded_rng(2);
+        // every order of three items comes up about equally often
+        let mut counts = std::collections::HashMap::new();
+        for _ in 0..6000 {
+            let mut items = [0, 1, 2];
+            shuffle(&mut rng, &mut items);
+            *counts.entry(items).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 6);
+        for &count in counts.values() {
+            assert!((850..1150).contains(&count), "{:?}", counts);
+        }
+
+        let mut items: Vec<usize> = (0..256).collect();
+        shuffle(&mut rng, &mut items);
+        assert_ne!(items, (0..256).collect::<Vec<_>>());
+        items.sort_unstable();
+        assert_eq!(items, (0..256).collect::<Vec<_>>());
+        shuffle(&mut rng, &mut [0u8; 0]);
+    }
 }
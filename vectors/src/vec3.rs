@@ -27,26 +27,35 @@ pub fn linear_to_gamma(value: f64) -> f64 {
     }
 }
 
+// Decodes an sRGB encoded value (as stored in most image files) to linear intensity.
+pub fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 pub fn near_zero(v: Vec3) -> bool {
     const S: f64 = 1e-8;
     v.0.abs() < S && v.1.abs() < S && v.2.abs() < S
 }
 
-pub fn random_with_range(min: f64, max: f64) -> Vec3 {
+pub fn random_with_range(rng: &mut RenderRng, min: f64, max: f64) -> Vec3 {
     Vec3(
-        random_double_with_range(min, max),
-        random_double_with_range(min, max),
-        random_double_with_range(min, max),
+        random_double_with_range(rng, min, max),
+        random_double_with_range(rng, min, max),
+        random_double_with_range(rng, min, max),
     )
 }
 
-fn random() -> Vec3 {
-    Vec3(random_double(), random_double(), random_double())
+pub fn random(rng: &mut RenderRng) -> Vec3 {
+    Vec3(random_double(rng), random_double(rng), random_double(rng))
 }
 
-pub fn random_unit_vector() -> Vec3 {
+pub fn random_unit_vector(rng: &mut RenderRng) -> Vec3 {
     loop {
-        let p = random_with_range(-1.0, 1.0);
+        let p = random_with_range(rng, -1.0, 1.0);
         let length_squared = p.length_squared();
         if 1e-160 < length_squared && length_squared < 1.0 {
             return p.unit_vector();
@@ -54,8 +63,21 @@ pub fn random_unit_vector() -> Vec3 {
     }
 }
 
-pub fn random_on_hemisphere(normal: Vec3) -> Vec3 {
-    let on_unit_sphere = random_unit_vector();
+pub fn random_in_unit_disk(rng: &mut RenderRng) -> Vec3 {
+    loop {
+        let p = Vec3(
+            random_double_with_range(rng, -1.0, 1.0),
+            random_double_with_range(rng, -1.0, 1.0),
+            0.0,
+        );
+        if p.length_squared() < 1.0 {
+            return p;
+        }
+    }
+}
+
+pub fn random_on_hemisphere(rng: &mut RenderRng, normal: Vec3) -> Vec3 {
+    let on_unit_sphere = random_unit_vector(rng);
     if on_unit_sphere.dot(normal) > 0.0 {
         on_unit_sphere
     } else {
@@ -104,13 +126,19 @@ impl Vec3 {
 
     const INTENSITY: Interval = Interval { min: 0.0, max: 1.0 };
 
-    pub fn get_color(&self) -> String {
+    // gamma corrected 8-bit components of a linear color
+    pub fn to_bytes(&self) -> [u8; 3] {
         let r = linear_to_gamma(self.x());
         let g = linear_to_gamma(self.y());
         let b = linear_to_gamma(self.z());
-        let r_byte = (255.999 * Self::INTENSITY.clamp(r)) as u64;
-        let g_byte = (255.999 * Self::INTENSITY.clamp(g)) as u64;
-        let b_byte = (255.999 * Self::INTENSITY.clamp(b)) as u64;
+        let r_byte = (255.999 * Self::INTENSITY.clamp(r)) as u8;
+        let g_byte = (255.999 * Self::INTENSITY.clamp(g)) as u8;
+        let b_byte = (255.999 * Self::INTENSITY.clamp(b)) as u8;
+        [r_byte, g_byte, b_byte]
+    }
+
+    pub fn get_color(&self) -> String {
+        let [r_byte, g_byte, b_byte] = self.to_bytes();
         format!("{} {} {}\n", r_byte, g_byte, b_byte)
     }
 }
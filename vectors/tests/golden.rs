@@ -0,0 +1,380 @@
+// Golden image regression tests.
+//
+// Each test renders a small scene with a fixed seed and compares it against the reference
+// image in tests/golden. When an image is too different, the render and an amplified
+// difference image are written next to the test binaries and their paths are reported.
+//
+// After an intended change to the renderer, regenerate the references with
+//   UPDATE_GOLDEN=1 cargo test --test golden
+// and check the new images before committing them.
+
+use std::path::PathBuf;
+use vectors::camera::*;
+use vectors::cuboid::*;
+use vectors::framebuffer::*;
+use vectors::hittable::*;
+use vectors::image::*;
+use vectors::material::*;
+use vectors::planar::*;
+use vectors::quadric::*;
+use vectors::texture::*;
+use vectors::torus::*;
+use vectors::vec3::*;
+
+const WIDTH: i32 = 64;
+const SAMPLES_PER_PIXEL: i32 = 16;
+const SEED: u64 = 2024;
+
+// root mean square difference allowed across all 8-bit channels
+const MAX_RMSE: f64 = 2.0;
+// share of pixels allowed to differ by more than OUTLIER_THRESHOLD in some channel
+const MAX_OUTLIER_RATIO: f64 = 0.005;
+const OUTLIER_THRESHOLD: u8 = 32;
+
+struct Image {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.ppm", name))
+}
+
+fn output_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("golden")
+        .join(name)
+}
+
+fn read_ppm(path: &PathBuf) -> Image {
+    let bytes = std::fs::read(path).unwrap_or_else(|err| {
+        panic!(
+            "{}: {} (run with UPDATE_GOLDEN=1 to create it)",
+            path.display(),
+            err
+        )
+    });
+
+    // Header fields are separated by whitespace, and a single whitespace byte
+    // follows the maximum value before the binary data starts.
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while fields.len() < 4 {
+        while bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        let start = position;
+        while !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        fields.push(String::from_utf8_lossy(&bytes[start..position]).to_string());
+    }
+    assert_eq!(fields[0], "P6", "{}: not a binary PPM", path.display());
+    assert_eq!(fields[3], "255", "{}: expected 8-bit data", path.display());
+
+    let width: usize = fields[1].parse().unwrap();
+    let height: usize = fields[2].parse().unwrap();
+    let rgb = bytes[position + 1..].to_vec();
+    assert_eq!(rgb.len(), width * height * 3, "{}: truncated", path.display());
+    Image { width, height, rgb }
+}
+
+fn write_ppm(path: &PathBuf, framebuffer: &Framebuffer) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut file = std::fs::File::create(path).unwrap();
+    BinaryPpmWriter.write(&mut file, framebuffer).unwrap();
+}
+
+fn write_diff(path: &PathBuf, expected: &Image, actual: &[u8]) {
+    let mut diff = Framebuffer::new(expected.width, expected.height);
+    for (i, pixel) in diff.pixels_mut().iter_mut().enumerate() {
+        let channel = |c: usize| {
+            let d = (expected.rgb[i * 3 + c] as f64 - actual[i * 3 + c] as f64).abs();
+            // amplified, and squared so the writer's gamma correction leaves it linear
+            ((d * 4.0) / 255.0).min(1.0).powi(2)
+        };
+        *pixel = Vec3(channel(0), channel(1), channel(2));
+    }
+    write_ppm(path, &diff);
+}
+
+fn check_golden(name: &str, mut camera: Camera, world: HittableList) {
+    camera.seed = SEED;
+    let framebuffer = camera.render(world);
+
+    let golden = golden_path(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        write_ppm(&golden, &framebuffer);
+        return;
+    }
+
+    let expected = read_ppm(&golden);
+    assert_eq!(
+        (expected.width, expected.height),
+        (framebuffer.width(), framebuffer.height()),
+        "{}: image size changed",
+        name
+    );
+
+    let actual = framebuffer.to_rgb8();
+    let squared_error: f64 = expected
+        .rgb
+        .iter()
+        .zip(actual.iter())
+        .map(|(&a, &b)| (a as f64 - b as f64).powi(2))
+        .sum();
+    let rmse = (squared_error / actual.len() as f64).sqrt();
+    let outliers = expected
+        .rgb
+        .chunks(3)
+        .zip(actual.chunks(3))
+        .filter(|(a, b)| {
+            a.iter()
+                .zip(b.iter())
+                .any(|(&a, &b)| a.abs_diff(b) > OUTLIER_THRESHOLD)
+        })
+        .count();
+    let outlier_ratio = outliers as f64 / (expected.width * expected.height) as f64;
+
+    if rmse > MAX_RMSE || outlier_ratio > MAX_OUTLIER_RATIO {
+        let actual_path = output_path(&format!("{}-actual.ppm", name));
+        let diff_path = output_path(&format!("{}-diff.ppm", name));
+        write_ppm(&actual_path, &framebuffer);
+        write_diff(&diff_path, &expected, &actual);
+        panic!(
+            "{}: rendered image differs from {} (rmse {:.3}, {:.2}% outlier pixels)\n  \
+             actual: {}\n  diff: {}",
+            name,
+            golden.display(),
+            rmse,
+            outlier_ratio * 100.0,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn camera() -> Camera {
+    let mut camera = Camera::new(
+        16.0 / 9.0,
+        40.0,
+        WIDTH,
+        SAMPLES_PER_PIXEL,
+        10,
+        Vec3(0.0, 0.5, 2.0),
+        Vec3(0.0, 0.0, -1.0),
+        Vec3(0.0, 1.0, 0.0),
+    );
+    camera.threads = 2;
+    camera
+}
+
+fn ground() -> HittableEnum {
+    HittableEnum::Sphere(Sphere::new(
+        Vec3(0.0, -100.5, -1.0),
+        100.0,
+        Material::Lambertian(Texture::Solid(Vec3(0.8, 0.8, 0.0))),
+    ))
+}
+
+#[test]
+fn lambertian() {
+    let world = HittableList {
+        objects: vec![
+            ground(),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(0.0, 0.0, -1.0),
+                0.5,
+                Material::Lambertian(Texture::Solid(Vec3(0.1, 0.2, 0.5))),
+            )),
+        ],
+    };
+    check_golden("lambertian", camera(), world);
+}
+
+#[test]
+fn metal() {
+    let world = HittableList {
+        objects: vec![
+            ground(),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(-0.55, 0.0, -1.0),
+                0.5,
+                Material::new_metal(Vec3(0.8, 0.8, 0.8), 0.0),
+            )),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(0.55, 0.0, -1.0),
+                0.5,
+                Material::new_metal(Vec3(0.8, 0.6, 0.2), 0.5),
+            )),
+        ],
+    };
+    check_golden("metal", camera(), world);
+}
+
+#[test]
+fn dielectric() {
+    let world = HittableList {
+        objects: vec![
+            ground(),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(-0.55, 0.0, -1.0),
+                0.5,
+                Material::Dielectric(1.5),
+            )),
+            // a hollow glass bubble
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(0.55, 0.0, -1.0),
+                0.5,
+                Material::Dielectric(1.5),
+            )),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(0.55, 0.0, -1.0),
+                0.4,
+                Material::Dielectric(1.0 / 1.5),
+            )),
+        ],
+    };
+    check_golden("dielectric", camera(), world);
+}
+
+#[test]
+fn noise_textures() {
+    let noise = |pattern, scale| {
+        Material::Lambertian(Texture::noise(
+            pattern,
+            7,
+            scale,
+            Vec3(0.1, 0.1, 0.15),
+            Vec3(0.9, 0.8, 0.6),
+        ))
+    };
+    let world = HittableList {
+        objects: vec![
+            ground(),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(-0.8, -0.15, -1.2),
+                0.35,
+                noise(NoisePattern::Turbulence, 4.0),
+            )),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(0.0, 0.0, -1.2),
+                0.5,
+                noise(NoisePattern::Marble, 8.0),
+            )),
+            HittableEnum::Sphere(Sphere::new(
+                Vec3(0.8, -0.15, -1.2),
+                0.35,
+                noise(NoisePattern::Wood, 10.0),
+            )),
+        ],
+    };
+    check_golden("noise_textures", camera(), world);
+}
+
+#[test]
+fn primitives() {
+    let lambertian = |r, g, b| Material::Lambertian(Texture::Solid(Vec3(r, g, b)));
+    // textured to show the orientation of the surface coordinates
+    let checker = |columns, rows, r, g, b| {
+        Material::Lambertian(Texture::uv_checker(
+            columns,
+            rows,
+            Vec3(r, g, b),
+            Vec3(0.9, 0.9, 0.9),
+        ))
+    };
+    let world = HittableList {
+        objects: vec![
+            HittableEnum::Plane(Plane::new(
+                Vec3(0.0, -0.5, 0.0),
+                Vec3(0.0, 1.0, 0.0),
+                Material::Lambertian(Texture::uv_checker(
+                    1.0,
+                    1.0,
+                    Vec3(0.8, 0.8, 0.8),
+                    Vec3(0.2, 0.3, 0.1),
+                )),
+            )),
+            // the back row
+            HittableEnum::Quad(Quad::new(
+                Vec3(-1.6, -0.5, -2.1),
+                Vec3(0.6, 0.0, 0.2),
+                Vec3(0.0, 0.9, -0.1),
+                checker(4.0, 2.0, 0.8, 0.2, 0.2),
+            )),
+            HittableEnum::Cuboid(
+                Cuboid::new(
+                    Vec3(-0.6, -0.5, -2.2),
+                    Vec3(-0.1, 0.0, -1.7),
+                    checker(2.0, 3.0, 0.1, 0.1, 0.4),
+                )
+                .rotated(Vec3(0.0, 1.0, 0.0), 30.0),
+            ),
+            // a truncated cone leaning back, its top cap in view
+            HittableEnum::Cone(
+                Cone::new(
+                    Vec3(0.35, -0.5, -2.0),
+                    Vec3(0.35, 0.2, -2.3),
+                    0.3,
+                    checker(8.0, 4.0, 0.2, 0.6, 0.2),
+                )
+                .with_top_radius(0.12),
+            ),
+            // standing upright, turned towards the camera
+            HittableEnum::Torus(
+                Torus::new(
+                    Vec3(1.0, -0.05, -2.2),
+                    0.3,
+                    0.1,
+                    Material::new_metal(Vec3(0.8, 0.8, 0.9), 0.05),
+                )
+                .with_axis(Vec3(-0.3, 0.1, 1.0)),
+            ),
+            // the front row
+            HittableEnum::Cylinder(Cylinder::new(
+                Vec3(-1.0, -0.5, -1.1),
+                Vec3(-1.0, -0.05, -1.1),
+                0.18,
+                checker(8.0, 4.0, 0.8, 0.6, 0.2),
+            )),
+            // cut sideways and closed with a cap facing the middle of the scene
+            HittableEnum::HalfSphere(
+                HalfSphere::new(
+                    Vec3(-0.5, -0.28, -1.0),
+                    0.22,
+                    lambertian(0.3, 0.8, 0.3),
+                    Vec3(-1.0, 0.0, 0.5),
+                )
+                .with_cap(true),
+            ),
+            // lying flat
+            HittableEnum::Torus(Torus::new(
+                Vec3(-0.05, -0.42, -0.85),
+                0.2,
+                0.08,
+                checker(12.0, 6.0, 0.8, 0.3, 0.2),
+            )),
+            // lying on the ground
+            HittableEnum::Capsule(Capsule::new(
+                Vec3(0.4, -0.38, -0.75),
+                Vec3(0.65, -0.38, -1.15),
+                0.12,
+                checker(8.0, 4.0, 0.2, 0.3, 0.8),
+            )),
+            HittableEnum::Disk(Disk::new(
+                Vec3(1.05, -0.2, -1.0),
+                Vec3(-0.5, 0.2, 1.0),
+                0.18,
+                Material::new_metal(Vec3(0.7, 0.7, 0.9), 0.1),
+            )),
+        ],
+    };
+    // zoomed in to fit the two rows
+    let mut camera = camera();
+    camera.vfov = 28.0;
+    check_golden("primitives", camera, world);
+}